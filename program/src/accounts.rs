@@ -0,0 +1,988 @@
+use solana_program::
+{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_program,
+};
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+use crate::error::TokenError;
+use crate::instruction::PauseScope;
+use crate::state::{
+    ExchangeState, EXCHANGE_STATE_SEED, FEE_VAULT_SEED, MANUAL_PRICE_SEED, MAX_PRICE_FEEDS, PAIR_CONFIG_SEED,
+    PRICE_HISTORY_SEED, TOKEN_CONFIG_SEED, USER_STATS_SEED
+};
+
+// Accounts of every instruction, in wire order. `from_account_infos` only takes them
+// off the list, `validate` checks everything that does not need program state
+// (signers, writability, owners, mints of token accounts, PDA addresses).
+// Exchange state account is split off by the processor and is not part of these.
+
+pub struct InitializeExchangeAccounts<'a, 'info> {
+    pub exchange_wallet: &'a AccountInfo<'info>,
+    pub exchange_state_account: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> InitializeExchangeAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            exchange_wallet: next_account_info(accounts_iter)?,
+            exchange_state_account: next_account_info(accounts_iter)?,
+            system_program: next_account_info(accounts_iter)?,
+        })
+    }
+
+    // returns bump seed of the exchange state address
+    pub fn validate(&self, program_id: &Pubkey) -> Result<u8, ProgramError> {
+        check_signer(self.exchange_wallet)?;
+        check_writable(self.exchange_wallet)?;
+        check_writable(self.exchange_state_account)?;
+        check_program(self.system_program, &system_program::id())?;
+        check_pda(self.exchange_state_account, &[EXCHANGE_STATE_SEED], program_id)
+    }
+}
+
+pub struct MigrateAccounts<'a, 'info> {
+    pub payer: &'a AccountInfo<'info>,
+    pub account: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> MigrateAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            payer: next_account_info(accounts_iter)?,
+            account: next_account_info(accounts_iter)?,
+            system_program: next_account_info(accounts_iter)?,
+        })
+    }
+
+    // address of the migrated account depends on its contents and is checked after upgrade
+    pub fn validate(&self, program_id: &Pubkey) -> ProgramResult {
+        check_signer(self.payer)?;
+        check_writable(self.payer)?;
+        check_writable(self.account)?;
+        check_program(self.system_program, &system_program::id())?;
+        if self.account.owner != program_id {
+            msg!("Account {} is not owned by the program", self.account.key);
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        Ok(())
+    }
+}
+
+pub struct DepositAccounts<'a, 'info> {
+    pub token_program: &'a AccountInfo<'info>,
+    pub mint: &'a AccountInfo<'info>,
+    pub exchange_wallet: &'a AccountInfo<'info>,
+    pub exchange_token_account: &'a AccountInfo<'info>,
+    pub pda_token_account: &'a AccountInfo<'info>,
+    pub exchange_program_account: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    pub token_config_account: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> DepositAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            token_program: next_account_info(accounts_iter)?,
+            mint: next_account_info(accounts_iter)?,
+            exchange_wallet: next_account_info(accounts_iter)?,
+            exchange_token_account: next_account_info(accounts_iter)?,
+            pda_token_account: next_account_info(accounts_iter)?,
+            exchange_program_account: next_account_info(accounts_iter)?,
+            system_program: next_account_info(accounts_iter)?,
+            token_config_account: next_account_info(accounts_iter)?,
+        })
+    }
+
+    // vault of a registered mint must be the registered one, otherwise it is created
+    // by this deposit and has to match the seeds
+    pub fn validate(
+        &self,
+        program_id: &Pubkey,
+        exchange_state: &ExchangeState,
+        pda_seed: &[u8; 16],
+        bump_seed: u8
+    ) -> ProgramResult {
+        check_program(self.token_program, &spl_token::id())?;
+        check_program(self.exchange_program_account, program_id)?;
+        check_program(self.system_program, &system_program::id())?;
+        check_mint(self.mint)?;
+        check_signer(self.exchange_wallet)?;
+        check_writable(self.exchange_wallet)?;
+        check_writable(self.exchange_token_account)?;
+        check_token_account(self.exchange_token_account, self.mint.key, Some(self.exchange_wallet.key))?;
+        check_writable(self.pda_token_account)?;
+
+        match exchange_state.find_mint(self.mint.key) {
+            Some(registered) => {
+                if registered.vault != *self.pda_token_account.key {
+                    msg!("PDA token account does not match registered vault");
+                    return Err(TokenError::MismatchedAccountsError.into());
+                }
+                check_token_account(self.pda_token_account, self.mint.key, Some(&registered.vault))?;
+            }
+            None => {
+                let vault_key = Pubkey::create_program_address(
+                    &[exchange_state.exchange_wallet.as_ref(), pda_seed, &[bump_seed]],
+                    program_id
+                )?;
+                if vault_key != *self.pda_token_account.key {
+                    msg!("PDA token account does not match seeds");
+                    return Err(TokenError::MismatchedAccountsError.into());
+                }
+                if !self.pda_token_account.data_is_empty() {
+                    msg!("PDA token account is already in use");
+                    return Err(ProgramError::AccountAlreadyInitialized);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct WithdrawAccounts<'a, 'info> {
+    pub token_program: &'a AccountInfo<'info>,
+    pub mint: &'a AccountInfo<'info>,
+    pub exchange_wallet: &'a AccountInfo<'info>,
+    pub exchange_token_account: &'a AccountInfo<'info>,
+    pub pda_token_account: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> WithdrawAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            token_program: next_account_info(accounts_iter)?,
+            mint: next_account_info(accounts_iter)?,
+            exchange_wallet: next_account_info(accounts_iter)?,
+            exchange_token_account: next_account_info(accounts_iter)?,
+            pda_token_account: next_account_info(accounts_iter)?,
+        })
+    }
+
+    pub fn validate(&self, exchange_state: &ExchangeState) -> ProgramResult {
+        check_program(self.token_program, &spl_token::id())?;
+        check_signer(self.exchange_wallet)?;
+        check_writable(self.exchange_token_account)?;
+        // funds only leave the vaults towards the exchange wallet, whoever the operator is
+        check_token_account(self.exchange_token_account, self.mint.key, Some(&exchange_state.exchange_wallet))?;
+        check_writable(self.pda_token_account)?;
+
+        let registered = match exchange_state.find_mint(self.mint.key) {
+            Some(registered) => registered,
+            None => {
+                msg!("No such mint account");
+                return Err(TokenError::MintMismatch.into());
+            }
+        };
+        if registered.vault != *self.pda_token_account.key {
+            msg!("PDA token account does not match registered vault");
+            return Err(TokenError::MismatchedAccountsError.into());
+        }
+        Ok(())
+    }
+}
+
+pub struct RegisterTokenAccounts<'a, 'info> {
+    pub admin: &'a AccountInfo<'info>,
+    pub mint: &'a AccountInfo<'info>,
+    pub token_config_account: &'a AccountInfo<'info>,
+    pub price_feed: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub fee_vault: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> RegisterTokenAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            admin: next_account_info(accounts_iter)?,
+            mint: next_account_info(accounts_iter)?,
+            token_config_account: next_account_info(accounts_iter)?,
+            price_feed: next_account_info(accounts_iter)?,
+            system_program: next_account_info(accounts_iter)?,
+            token_program: next_account_info(accounts_iter)?,
+            fee_vault: next_account_info(accounts_iter)?,
+        })
+    }
+
+    // returns bump seeds of the token config and fee vault addresses
+    pub fn validate(&self, program_id: &Pubkey) -> Result<(u8, u8), ProgramError> {
+        check_signer(self.admin)?;
+        check_writable(self.admin)?;
+        check_mint(self.mint)?;
+        check_writable(self.token_config_account)?;
+        check_program(self.system_program, &system_program::id())?;
+        check_program(self.token_program, &spl_token::id())?;
+        check_writable(self.fee_vault)?;
+        let token_config_bump =
+            check_pda(self.token_config_account, &[TOKEN_CONFIG_SEED, self.mint.key.as_ref()], program_id)?;
+        let fee_vault_bump = check_pda(self.fee_vault, &[FEE_VAULT_SEED, self.mint.key.as_ref()], program_id)?;
+        Ok((token_config_bump, fee_vault_bump))
+    }
+}
+
+// admin changing a setting of a registered token
+pub struct UpdateTokenConfigAccounts<'a, 'info> {
+    pub admin: &'a AccountInfo<'info>,
+    pub mint: &'a AccountInfo<'info>,
+    pub token_config_account: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> UpdateTokenConfigAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            admin: next_account_info(accounts_iter)?,
+            mint: next_account_info(accounts_iter)?,
+            token_config_account: next_account_info(accounts_iter)?,
+        })
+    }
+
+    // token config address is checked when it is loaded
+    pub fn validate(&self) -> ProgramResult {
+        check_signer(self.admin)?;
+        check_writable(self.token_config_account)
+    }
+}
+
+pub struct SetOracleAccounts<'a, 'info> {
+    pub admin: &'a AccountInfo<'info>,
+    pub mint: &'a AccountInfo<'info>,
+    pub token_config_account: &'a AccountInfo<'info>,
+    pub price_feed: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> SetOracleAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            admin: next_account_info(accounts_iter)?,
+            mint: next_account_info(accounts_iter)?,
+            token_config_account: next_account_info(accounts_iter)?,
+            price_feed: next_account_info(accounts_iter)?,
+        })
+    }
+
+    pub fn validate(&self) -> ProgramResult {
+        check_signer(self.admin)?;
+        check_writable(self.token_config_account)
+    }
+}
+
+pub struct SetPriceFeedsAccounts<'a, 'info> {
+    pub admin: &'a AccountInfo<'info>,
+    pub mint: &'a AccountInfo<'info>,
+    pub token_config_account: &'a AccountInfo<'info>,
+    pub price_feeds: &'a [AccountInfo<'info>],
+}
+
+impl<'a, 'info> SetPriceFeedsAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            admin: next_account_info(accounts_iter)?,
+            mint: next_account_info(accounts_iter)?,
+            token_config_account: next_account_info(accounts_iter)?,
+            price_feeds: accounts_iter.as_slice(),
+        })
+    }
+
+    // one feed account per provider, each feed at most once
+    pub fn validate(&self, feeds: usize) -> ProgramResult {
+        check_signer(self.admin)?;
+        check_writable(self.token_config_account)?;
+        if feeds == 0 || feeds > MAX_PRICE_FEEDS || self.price_feeds.len() != feeds {
+            msg!(
+                "Expected 1 to {} feeds, got {} providers and {} accounts",
+                MAX_PRICE_FEEDS,
+                feeds,
+                self.price_feeds.len()
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+        for (i, price_feed) in self.price_feeds.iter().enumerate() {
+            if self.price_feeds[..i].iter().any(|other| other.key == price_feed.key) {
+                msg!("Feed {} is passed more than once", price_feed.key);
+                return Err(ProgramError::InvalidArgument);
+            }
+        }
+        Ok(())
+    }
+}
+
+// what pricing a swap reads, same order as in SwapAccounts
+#[allow(non_snake_case)]
+#[derive(Clone, Copy)]
+pub struct QuoteAccounts<'a, 'info> {
+    pub mint_A: &'a AccountInfo<'info>,
+    pub mint_B: &'a AccountInfo<'info>,
+    pub token_A_data_feed_account: &'a AccountInfo<'info>,
+    pub token_B_data_feed_account: &'a AccountInfo<'info>,
+    pub chainlink_program: &'a AccountInfo<'info>,
+    pub token_A_config_account: &'a AccountInfo<'info>,
+    pub token_B_config_account: &'a AccountInfo<'info>,
+    pub pair_config_account: &'a AccountInfo<'info>,
+    pub token_A_price_history_account: &'a AccountInfo<'info>,
+    pub token_B_price_history_account: &'a AccountInfo<'info>,
+    // UserStats of the client the fee tier is looked up for, the program id when there is none
+    pub client_stats_account: Option<&'a AccountInfo<'info>>,
+    pub extra_feed_accounts: &'a [AccountInfo<'info>],
+}
+
+impl<'a, 'info> QuoteAccounts<'a, 'info> {
+    pub fn from_account_infos(
+        accounts: &'a [AccountInfo<'info>],
+        program_id: &Pubkey
+    ) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            mint_A: next_account_info(accounts_iter)?,
+            mint_B: next_account_info(accounts_iter)?,
+            token_A_data_feed_account: next_account_info(accounts_iter)?,
+            token_B_data_feed_account: next_account_info(accounts_iter)?,
+            chainlink_program: next_account_info(accounts_iter)?,
+            token_A_config_account: next_account_info(accounts_iter)?,
+            token_B_config_account: next_account_info(accounts_iter)?,
+            pair_config_account: next_account_info(accounts_iter)?,
+            token_A_price_history_account: next_account_info(accounts_iter)?,
+            token_B_price_history_account: next_account_info(accounts_iter)?,
+            client_stats_account: Some(next_account_info(accounts_iter)?)
+                .filter(|account| account.key != program_id),
+            extra_feed_accounts: accounts_iter.as_slice(),
+        })
+    }
+
+    // nothing is written, configs are checked when they are loaded, client stats as well
+    pub fn validate(&self) -> ProgramResult {
+        check_mint(self.mint_A)?;
+        check_mint(self.mint_B)?;
+        if self.mint_A.key == self.mint_B.key {
+            msg!("Cannot exchange token to itself");
+            return Err(TokenError::MintMismatch.into());
+        }
+        Ok(())
+    }
+}
+
+pub struct UpdatePriceAccounts<'a, 'info> {
+    pub payer: &'a AccountInfo<'info>,
+    pub mint: &'a AccountInfo<'info>,
+    pub token_config_account: &'a AccountInfo<'info>,
+    pub price_history_account: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    pub data_feed_account: &'a AccountInfo<'info>,
+    pub oracle_program: &'a AccountInfo<'info>,
+    pub extra_feed_accounts: &'a [AccountInfo<'info>],
+}
+
+impl<'a, 'info> UpdatePriceAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            payer: next_account_info(accounts_iter)?,
+            mint: next_account_info(accounts_iter)?,
+            token_config_account: next_account_info(accounts_iter)?,
+            price_history_account: next_account_info(accounts_iter)?,
+            system_program: next_account_info(accounts_iter)?,
+            data_feed_account: next_account_info(accounts_iter)?,
+            oracle_program: next_account_info(accounts_iter)?,
+            extra_feed_accounts: accounts_iter.as_slice(),
+        })
+    }
+
+    // returns bump seed of the price history address, feeds are checked when they are read
+    pub fn validate(&self, program_id: &Pubkey) -> Result<u8, ProgramError> {
+        check_signer(self.payer)?;
+        check_writable(self.payer)?;
+        check_writable(self.price_history_account)?;
+        check_program(self.system_program, &system_program::id())?;
+        check_pda(self.price_history_account, &[PRICE_HISTORY_SEED, self.mint.key.as_ref()], program_id)
+    }
+}
+
+pub struct SetManualPriceAccounts<'a, 'info> {
+    pub operator: &'a AccountInfo<'info>,
+    pub mint: &'a AccountInfo<'info>,
+    pub token_config_account: &'a AccountInfo<'info>,
+    pub manual_price_account: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> SetManualPriceAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            operator: next_account_info(accounts_iter)?,
+            mint: next_account_info(accounts_iter)?,
+            token_config_account: next_account_info(accounts_iter)?,
+            manual_price_account: next_account_info(accounts_iter)?,
+            system_program: next_account_info(accounts_iter)?,
+        })
+    }
+
+    // returns bump seed of the manual price address
+    pub fn validate(&self, program_id: &Pubkey) -> Result<u8, ProgramError> {
+        check_signer(self.operator)?;
+        check_writable(self.operator)?;
+        check_writable(self.manual_price_account)?;
+        check_program(self.system_program, &system_program::id())?;
+        check_pda(self.manual_price_account, &[MANUAL_PRICE_SEED, self.mint.key.as_ref()], program_id)
+    }
+}
+
+pub struct InitializeFeeVaultAccounts<'a, 'info> {
+    pub payer: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub mint: &'a AccountInfo<'info>,
+    pub token_config_account: &'a AccountInfo<'info>,
+    pub fee_vault: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> InitializeFeeVaultAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            payer: next_account_info(accounts_iter)?,
+            token_program: next_account_info(accounts_iter)?,
+            mint: next_account_info(accounts_iter)?,
+            token_config_account: next_account_info(accounts_iter)?,
+            fee_vault: next_account_info(accounts_iter)?,
+            system_program: next_account_info(accounts_iter)?,
+        })
+    }
+
+    // returns bump seed of the fee vault address
+    pub fn validate(&self, program_id: &Pubkey) -> Result<u8, ProgramError> {
+        check_signer(self.payer)?;
+        check_writable(self.payer)?;
+        check_program(self.token_program, &spl_token::id())?;
+        check_mint(self.mint)?;
+        check_writable(self.token_config_account)?;
+        check_writable(self.fee_vault)?;
+        check_program(self.system_program, &system_program::id())?;
+        check_pda(self.fee_vault, &[FEE_VAULT_SEED, self.mint.key.as_ref()], program_id)
+    }
+}
+
+pub struct WithdrawFeesAccounts<'a, 'info> {
+    pub admin: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub mint: &'a AccountInfo<'info>,
+    pub token_config_account: &'a AccountInfo<'info>,
+    pub fee_vault: &'a AccountInfo<'info>,
+    pub destination_token_account: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> WithdrawFeesAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            admin: next_account_info(accounts_iter)?,
+            token_program: next_account_info(accounts_iter)?,
+            mint: next_account_info(accounts_iter)?,
+            token_config_account: next_account_info(accounts_iter)?,
+            fee_vault: next_account_info(accounts_iter)?,
+            destination_token_account: next_account_info(accounts_iter)?,
+        })
+    }
+
+    // fee vault is checked against the token config
+    pub fn validate(&self) -> ProgramResult {
+        check_signer(self.admin)?;
+        check_program(self.token_program, &spl_token::id())?;
+        check_mint(self.mint)?;
+        check_writable(self.token_config_account)?;
+        check_writable(self.fee_vault)?;
+        check_writable(self.destination_token_account)?;
+        check_token_account(self.destination_token_account, self.mint.key, None)?;
+        Ok(())
+    }
+}
+
+pub struct InitializeUserStatsAccounts<'a, 'info> {
+    pub payer: &'a AccountInfo<'info>,
+    pub wallet: &'a AccountInfo<'info>,
+    pub user_stats_account: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> InitializeUserStatsAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            payer: next_account_info(accounts_iter)?,
+            wallet: next_account_info(accounts_iter)?,
+            user_stats_account: next_account_info(accounts_iter)?,
+            system_program: next_account_info(accounts_iter)?,
+        })
+    }
+
+    // returns bump seed of the user stats address
+    pub fn validate(&self, program_id: &Pubkey) -> Result<u8, ProgramError> {
+        check_signer(self.payer)?;
+        check_writable(self.payer)?;
+        check_writable(self.user_stats_account)?;
+        check_program(self.system_program, &system_program::id())?;
+        check_pda(self.user_stats_account, &[USER_STATS_SEED, self.wallet.key.as_ref()], program_id)
+    }
+}
+
+// admin changing an exchange wide setting
+pub struct UpdateExchangeStateAccounts<'a, 'info> {
+    pub admin: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> UpdateExchangeStateAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            admin: next_account_info(accounts_iter)?,
+        })
+    }
+
+    pub fn validate(&self) -> ProgramResult {
+        check_signer(self.admin)
+    }
+}
+
+pub struct RegisterPairAccounts<'a, 'info> {
+    pub admin: &'a AccountInfo<'info>,
+    pub base_mint: &'a AccountInfo<'info>,
+    pub quote_mint: &'a AccountInfo<'info>,
+    pub base_config_account: &'a AccountInfo<'info>,
+    pub quote_config_account: &'a AccountInfo<'info>,
+    pub pair_config_account: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> RegisterPairAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            admin: next_account_info(accounts_iter)?,
+            base_mint: next_account_info(accounts_iter)?,
+            quote_mint: next_account_info(accounts_iter)?,
+            base_config_account: next_account_info(accounts_iter)?,
+            quote_config_account: next_account_info(accounts_iter)?,
+            pair_config_account: next_account_info(accounts_iter)?,
+            system_program: next_account_info(accounts_iter)?,
+        })
+    }
+
+    // returns bump seed of the pair config address
+    pub fn validate(&self, program_id: &Pubkey) -> Result<u8, ProgramError> {
+        check_signer(self.admin)?;
+        check_writable(self.admin)?;
+        check_mint(self.base_mint)?;
+        check_mint(self.quote_mint)?;
+        if self.base_mint.key == self.quote_mint.key {
+            msg!("Pair needs two different mints");
+            return Err(TokenError::MintMismatch.into());
+        }
+        check_writable(self.pair_config_account)?;
+        check_program(self.system_program, &system_program::id())?;
+        check_pda(
+            self.pair_config_account,
+            &[PAIR_CONFIG_SEED, self.base_mint.key.as_ref(), self.quote_mint.key.as_ref()],
+            program_id
+        )
+    }
+}
+
+pub struct UpdatePairConfigAccounts<'a, 'info> {
+    pub admin: &'a AccountInfo<'info>,
+    pub pair_config_account: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> UpdatePairConfigAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        Ok(Self {
+            admin: next_account_info(accounts_iter)?,
+            pair_config_account: next_account_info(accounts_iter)?,
+        })
+    }
+
+    pub fn validate(&self) -> ProgramResult {
+        check_signer(self.admin)?;
+        check_writable(self.pair_config_account)
+    }
+}
+
+// which accounts follow the authority depends on the scope
+pub enum PauseTargetAccounts<'a, 'info> {
+    Global,
+    Mint {
+        mint: &'a AccountInfo<'info>,
+        token_config_account: &'a AccountInfo<'info>,
+    },
+    Pair {
+        pair_config_account: &'a AccountInfo<'info>,
+    },
+}
+
+pub struct PauseAccounts<'a, 'info> {
+    pub authority: &'a AccountInfo<'info>,
+    pub target: PauseTargetAccounts<'a, 'info>,
+}
+
+impl<'a, 'info> PauseAccounts<'a, 'info> {
+    pub fn from_account_infos(
+        accounts: &'a [AccountInfo<'info>],
+        scope: PauseScope
+    ) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        let authority = next_account_info(accounts_iter)?;
+        let target = match scope {
+            PauseScope::Global => PauseTargetAccounts::Global,
+            PauseScope::Mint => PauseTargetAccounts::Mint {
+                mint: next_account_info(accounts_iter)?,
+                token_config_account: next_account_info(accounts_iter)?,
+            },
+            PauseScope::Pair => PauseTargetAccounts::Pair {
+                pair_config_account: next_account_info(accounts_iter)?,
+            },
+        };
+        Ok(Self { authority, target })
+    }
+
+    pub fn validate(&self) -> ProgramResult {
+        check_signer(self.authority)?;
+        match self.target {
+            PauseTargetAccounts::Global => Ok(()),
+            PauseTargetAccounts::Mint { token_config_account, .. } => check_writable(token_config_account),
+            PauseTargetAccounts::Pair { pair_config_account } => check_writable(pair_config_account),
+        }
+    }
+}
+
+// shared by all swap instructions
+#[allow(non_snake_case)]
+pub struct SwapAccounts<'a, 'info> {
+    pub token_program: &'a AccountInfo<'info>,
+    pub exchange_program_account: &'a AccountInfo<'info>,
+    pub mint_A: &'a AccountInfo<'info>,
+    pub mint_B: &'a AccountInfo<'info>,
+    pub client_wallet: &'a AccountInfo<'info>,
+    pub client_token_A_account: &'a AccountInfo<'info>,
+    pub client_token_B_account: &'a AccountInfo<'info>,
+    pub exchange_wallet: &'a AccountInfo<'info>,
+    pub exchange_token_A_account: &'a AccountInfo<'info>,
+    pub exchange_token_B_account: &'a AccountInfo<'info>,
+    // аккаунты, хранящие данные по стоимости token A, token B
+    pub token_A_data_feed_account: &'a AccountInfo<'info>,
+    pub token_B_data_feed_account: &'a AccountInfo<'info>,
+    // программа, взаимодействующая с oracles
+    pub chainlink_program: &'a AccountInfo<'info>,
+    pub token_A_config_account: &'a AccountInfo<'info>,
+    pub token_B_config_account: &'a AccountInfo<'info>,
+    pub pair_config_account: &'a AccountInfo<'info>,
+    pub token_A_price_history_account: &'a AccountInfo<'info>,
+    pub token_B_price_history_account: &'a AccountInfo<'info>,
+    // where the fee in token B goes
+    pub token_B_fee_vault: &'a AccountInfo<'info>,
+    // token account of B of an allowlisted referrer, the program id when there is none
+    pub referrer_token_account: Option<&'a AccountInfo<'info>>,
+    // UserStats of the client wallet, the program id when the swap is not tracked
+    pub client_stats_account: Option<&'a AccountInfo<'info>>,
+    // extra feeds of token A then of token B, in token config order
+    pub extra_feed_accounts: &'a [AccountInfo<'info>],
+}
+
+impl<'a, 'info> SwapAccounts<'a, 'info> {
+    pub fn from_account_infos(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+        let token_program = next_account_info(accounts_iter)?;
+        let exchange_program_account = next_account_info(accounts_iter)?;
+        Ok(Self {
+            token_program,
+            exchange_program_account,
+            mint_A: next_account_info(accounts_iter)?,
+            mint_B: next_account_info(accounts_iter)?,
+            client_wallet: next_account_info(accounts_iter)?,
+            client_token_A_account: next_account_info(accounts_iter)?,
+            client_token_B_account: next_account_info(accounts_iter)?,
+            exchange_wallet: next_account_info(accounts_iter)?,
+            exchange_token_A_account: next_account_info(accounts_iter)?,
+            exchange_token_B_account: next_account_info(accounts_iter)?,
+            token_A_data_feed_account: next_account_info(accounts_iter)?,
+            token_B_data_feed_account: next_account_info(accounts_iter)?,
+            chainlink_program: next_account_info(accounts_iter)?,
+            token_A_config_account: next_account_info(accounts_iter)?,
+            token_B_config_account: next_account_info(accounts_iter)?,
+            pair_config_account: next_account_info(accounts_iter)?,
+            token_A_price_history_account: next_account_info(accounts_iter)?,
+            token_B_price_history_account: next_account_info(accounts_iter)?,
+            token_B_fee_vault: next_account_info(accounts_iter)?,
+            referrer_token_account: Some(next_account_info(accounts_iter)?)
+                .filter(|account| account.key != exchange_program_account.key),
+            client_stats_account: Some(next_account_info(accounts_iter)?)
+                .filter(|account| account.key != exchange_program_account.key),
+            extra_feed_accounts: accounts_iter.as_slice(),
+        })
+    }
+
+    pub fn quote_accounts(&self) -> QuoteAccounts<'a, 'info> {
+        QuoteAccounts {
+            mint_A: self.mint_A,
+            mint_B: self.mint_B,
+            token_A_data_feed_account: self.token_A_data_feed_account,
+            token_B_data_feed_account: self.token_B_data_feed_account,
+            chainlink_program: self.chainlink_program,
+            token_A_config_account: self.token_A_config_account,
+            token_B_config_account: self.token_B_config_account,
+            pair_config_account: self.pair_config_account,
+            token_A_price_history_account: self.token_A_price_history_account,
+            token_B_price_history_account: self.token_B_price_history_account,
+            client_stats_account: self.client_stats_account,
+            extra_feed_accounts: self.extra_feed_accounts,
+        }
+    }
+
+    // token and pair configs are checked when they are loaded, feeds when they are read,
+    // the fee vault against the token config of B
+    pub fn validate(&self, program_id: &Pubkey, exchange_state: &ExchangeState) -> ProgramResult {
+        check_program(self.token_program, &spl_token::id())?;
+        check_program(self.exchange_program_account, program_id)?;
+        check_mint(self.mint_A)?;
+        check_mint(self.mint_B)?;
+        if self.mint_A.key == self.mint_B.key {
+            msg!("Cannot exchange token to itself");
+            return Err(TokenError::MintMismatch.into());
+        }
+
+        check_signer(self.client_wallet)?;
+        check_signer(self.exchange_wallet)?;
+        if *self.exchange_wallet.key != exchange_state.exchange_wallet {
+            msg!("Exchange wallet {} does not belong to the exchange", self.exchange_wallet.key);
+            return Err(TokenError::MismatchedAccountsError.into());
+        }
+
+        for (token_account, mint, owner) in [
+            (self.client_token_A_account, self.mint_A, self.client_wallet),
+            (self.client_token_B_account, self.mint_B, self.client_wallet),
+            (self.exchange_token_A_account, self.mint_A, self.exchange_wallet),
+            (self.exchange_token_B_account, self.mint_B, self.exchange_wallet),
+        ] {
+            check_writable(token_account)?;
+            check_token_account(token_account, mint.key, Some(owner.key))?;
+        }
+        check_writable(self.token_B_config_account)?;
+        check_writable(self.pair_config_account)?;
+        check_writable(self.token_A_price_history_account)?;
+        check_writable(self.token_B_price_history_account)?;
+        check_writable(self.token_B_fee_vault)?;
+
+        if let Some(referrer_token_account) = self.referrer_token_account {
+            check_writable(referrer_token_account)?;
+            let referrer = check_token_account(referrer_token_account, self.mint_B.key, None)?.owner;
+            if !exchange_state.is_referrer(&referrer) {
+                msg!("{} is not an allowlisted referrer", referrer);
+                return Err(TokenError::ReferrerNotAllowed.into());
+            }
+        }
+        if let Some(client_stats_account) = self.client_stats_account {
+            check_writable(client_stats_account)?;
+        }
+        Ok(())
+    }
+}
+
+fn check_signer(account: &AccountInfo) -> ProgramResult {
+    if !account.is_signer {
+        msg!("Missing required signature of {}", account.key);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
+fn check_writable(account: &AccountInfo) -> ProgramResult {
+    if !account.is_writable {
+        msg!("Account {} must be writable", account.key);
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+fn check_program(account: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+    if account.key != program_id {
+        msg!("Expected program {}, got {}", program_id, account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+fn check_pda(account: &AccountInfo, seeds: &[&[u8]], program_id: &Pubkey) -> Result<u8, ProgramError> {
+    let (key, bump_seed) = Pubkey::find_program_address(seeds, program_id);
+    if key != *account.key {
+        msg!("Account {} address mismatch", account.key);
+        return Err(TokenError::MismatchedAccountsError.into());
+    }
+    Ok(bump_seed)
+}
+
+fn check_mint(mint: &AccountInfo) -> Result<Mint, ProgramError> {
+    if mint.owner != &spl_token::id() {
+        msg!("Mint {} is not owned by token program", mint.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Mint::unpack(&mint.data.borrow()).map_err(|_| {
+        msg!("Account {} is not a mint", mint.key);
+        TokenError::MintMismatch.into()
+    })
+}
+
+// token account of `mint`, not frozen and, if given, owned by `owner`
+fn check_token_account(
+    account: &AccountInfo,
+    mint: &Pubkey,
+    owner: Option<&Pubkey>
+) -> Result<TokenAccount, ProgramError> {
+    if account.owner != &spl_token::id() {
+        msg!("Token account {} is not owned by token program", account.key);
+        return Err(TokenError::InvalidAssociatedTokenAccount.into());
+    }
+    let token_account = TokenAccount::unpack(&account.data.borrow()).map_err(|_| {
+        msg!("Account {} is not a token account", account.key);
+        ProgramError::from(TokenError::InvalidAssociatedTokenAccount)
+    })?;
+    if token_account.mint != *mint {
+        msg!("Token account {} does not belong to mint {}", account.key, mint);
+        return Err(TokenError::MintMismatch.into());
+    }
+    if let Some(owner) = owner {
+        if token_account.owner != *owner {
+            msg!("Token account {} is not owned by {}", account.key, owner);
+            return Err(TokenError::MismatchedAccountsError.into());
+        }
+    }
+    if token_account.state == AccountState::Frozen {
+        msg!("Token account {} is frozen", account.key);
+        return Err(TokenError::AccountFrozen.into());
+    }
+    Ok(token_account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::RegisteredMint;
+
+    fn token_account_data(mint: Pubkey, owner: Pubkey, state: AccountState) -> Vec<u8> {
+        let mut data = vec![0; TokenAccount::LEN];
+        TokenAccount {
+            mint,
+            owner,
+            state,
+            ..TokenAccount::default()
+        }.pack_into_slice(&mut data);
+        data
+    }
+
+    fn check(
+        data: &mut [u8],
+        account_owner: &Pubkey,
+        mint: &Pubkey,
+        owner: Option<&Pubkey>
+    ) -> Result<TokenAccount, ProgramError> {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let account = AccountInfo::new(&key, false, true, &mut lamports, data, account_owner, false, 0);
+        check_token_account(&account, mint, owner)
+    }
+
+    #[test]
+    fn accepts_token_account_of_mint_and_owner() {
+        let (mint, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = token_account_data(mint, owner, AccountState::Initialized);
+        let token_account = check(&mut data, &spl_token::id(), &mint, Some(&owner)).unwrap();
+        assert_eq!(token_account.owner, owner);
+    }
+
+    #[test]
+    fn rejects_account_not_owned_by_token_program() {
+        let (mint, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = token_account_data(mint, owner, AccountState::Initialized);
+        assert_eq!(
+            check(&mut data, &Pubkey::new_unique(), &mint, Some(&owner)),
+            Err(TokenError::InvalidAssociatedTokenAccount.into())
+        );
+    }
+
+    #[test]
+    fn rejects_token_account_of_other_mint_or_owner() {
+        let (mint, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = token_account_data(mint, owner, AccountState::Initialized);
+        assert_eq!(
+            check(&mut data, &spl_token::id(), &Pubkey::new_unique(), Some(&owner)),
+            Err(TokenError::MintMismatch.into())
+        );
+        assert_eq!(
+            check(&mut data, &spl_token::id(), &mint, Some(&Pubkey::new_unique())),
+            Err(TokenError::MismatchedAccountsError.into())
+        );
+        assert!(check(&mut data, &spl_token::id(), &mint, None).is_ok());
+    }
+
+    fn validate_withdraw(exchange_state: &ExchangeState, signer: &Pubkey, destination_owner: &Pubkey) -> ProgramResult {
+        let registered = &exchange_state.mints[0];
+        let (token_program, destination) = (spl_token::id(), Pubkey::new_unique());
+        let mut lamports = [0; 5];
+        let [token_program_lamports, mint_lamports, signer_lamports, destination_lamports, vault_lamports] =
+            &mut lamports;
+        let (mut token_program_data, mut mint_data, mut signer_data, mut vault_data) =
+            (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        let mut destination_data = token_account_data(registered.mint, *destination_owner, AccountState::Initialized);
+        let infos = [
+            AccountInfo::new(&token_program, false, false, token_program_lamports, &mut token_program_data, &token_program, true, 0),
+            AccountInfo::new(&registered.mint, false, false, mint_lamports, &mut mint_data, &token_program, false, 0),
+            AccountInfo::new(signer, true, false, signer_lamports, &mut signer_data, &token_program, false, 0),
+            AccountInfo::new(&destination, false, true, destination_lamports, &mut destination_data, &token_program, false, 0),
+            AccountInfo::new(&registered.vault, false, true, vault_lamports, &mut vault_data, &token_program, false, 0),
+        ];
+        WithdrawAccounts::from_account_infos(&infos)?.validate(exchange_state)
+    }
+
+    #[test]
+    fn withdraw_destination_must_belong_to_exchange_wallet() {
+        let (exchange_wallet, operator) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let exchange_state = ExchangeState {
+            exchange_wallet,
+            operator,
+            mints: vec![RegisteredMint {
+                mint: Pubkey::new_unique(),
+                vault: Pubkey::new_unique(),
+                vault_seed: [0; 16],
+                vault_bump: 0,
+            }],
+            ..ExchangeState::default()
+        };
+        assert!(validate_withdraw(&exchange_state, &operator, &exchange_wallet).is_ok());
+        assert_eq!(
+            validate_withdraw(&exchange_state, &operator, &operator),
+            Err(TokenError::MismatchedAccountsError.into())
+        );
+    }
+
+    #[test]
+    fn rejects_frozen_token_account() {
+        let (mint, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = token_account_data(mint, owner, AccountState::Frozen);
+        assert_eq!(
+            check(&mut data, &spl_token::id(), &mint, Some(&owner)),
+            Err(TokenError::AccountFrozen.into())
+        );
+    }
+}
@@ -15,8 +15,7 @@ fn process_instruction<'a>(
     instruction_data: &'a [u8],
 ) -> ProgramResult {
 
-    let mut processor = Processor {token_mints : Default::default()};
-    if let Err(error) = processor.process_instruction(program_id, accounts, instruction_data) {
+    if let Err(error) = Processor::process_instruction(program_id, accounts, instruction_data) {
         error.print::<TokenError>();
         return Err(error);
     }
@@ -23,6 +23,42 @@ pub enum TokenError {
     MintMismatch,
     #[error("Operation overflowed")]
     Overflow,
+    #[error("Oracle feed is not the one registered for this mint")]
+    OracleFeedMismatch,
+    #[error("Token is disabled")]
+    TokenDisabled,
+    #[error("Signer is not authorized for this operation")]
+    Unauthorized,
+    #[error("Exchange is paused")]
+    ExchangePaused,
+    #[error("Token is paused")]
+    TokenPaused,
+    #[error("Trading pair is paused")]
+    PairPaused,
+    #[error("Account has an outdated layout and needs to be migrated")]
+    AccountNeedsMigration,
+    #[error("Oracle price is too old or from the future")]
+    OracleStale,
+    #[error("Oracle returned a non-positive price")]
+    OracleInvalidPrice,
+    #[error("Oracle is not publishing a tradable price")]
+    OraclePriceUnavailable,
+    #[error("Oracle confidence interval is too wide")]
+    OracleConfidenceTooWide,
+    #[error("Not enough oracle feeds returned a valid price")]
+    OracleQuorumNotMet,
+    #[error("Not enough price history for TWAP")]
+    TwapUnavailable,
+    #[error("Spot price deviates too far from TWAP")]
+    PriceDeviatesFromTwap,
+    #[error("Price moved past the circuit breaker, pair is paused")]
+    CircuitBreakerTripped,
+    #[error("Swap output is below the requested minimum or input above the maximum")]
+    SlippageExceeded,
+    #[error("Fee vault of the mint is not initialized")]
+    FeeVaultNotInitialized,
+    #[error("Referrer is not on the allowlist")]
+    ReferrerNotAllowed,
 }
 
 impl From<TokenError> for ProgramError {
@@ -56,6 +92,24 @@ impl PrintProgramError for TokenError {
             TokenError::InsufficientFunds => msg!("Error: Insufficient funds"),
             TokenError::MintMismatch => msg!("Error: Account not associated with this Mint"),
             TokenError::Overflow => msg!("Error: Operation overflowed"),
+            TokenError::OracleFeedMismatch => msg!("Error: Oracle feed is not the one registered for this mint"),
+            TokenError::TokenDisabled => msg!("Error: Token is disabled"),
+            TokenError::Unauthorized => msg!("Error: Signer is not authorized for this operation"),
+            TokenError::ExchangePaused => msg!("Error: Exchange is paused"),
+            TokenError::TokenPaused => msg!("Error: Token is paused"),
+            TokenError::PairPaused => msg!("Error: Trading pair is paused"),
+            TokenError::AccountNeedsMigration => msg!("Error: Account has an outdated layout and needs to be migrated"),
+            TokenError::OracleStale => msg!("Error: Oracle price is too old or from the future"),
+            TokenError::OracleInvalidPrice => msg!("Error: Oracle returned a non-positive price"),
+            TokenError::OraclePriceUnavailable => msg!("Error: Oracle is not publishing a tradable price"),
+            TokenError::OracleConfidenceTooWide => msg!("Error: Oracle confidence interval is too wide"),
+            TokenError::OracleQuorumNotMet => msg!("Error: Not enough oracle feeds returned a valid price"),
+            TokenError::TwapUnavailable => msg!("Error: Not enough price history for TWAP"),
+            TokenError::PriceDeviatesFromTwap => msg!("Error: Spot price deviates too far from TWAP"),
+            TokenError::CircuitBreakerTripped => msg!("Error: Price moved past the circuit breaker, pair is paused"),
+            TokenError::SlippageExceeded => msg!("Error: Swap output is below the requested minimum or input above the maximum"),
+            TokenError::FeeVaultNotInitialized => msg!("Error: Fee vault of the mint is not initialized"),
+            TokenError::ReferrerNotAllowed => msg!("Error: Referrer is not on the allowlist"),
         }
     }
 }
\ No newline at end of file
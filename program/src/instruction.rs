@@ -1,52 +1,381 @@
-use borsh::BorshDeserialize;
+use std::io::{self, Read};
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::msg;
 use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use crate::state::{AccountType, FeeTier, OracleProvider, PriceMode, Role};
 
+// first byte of instruction data, bumped on incompatible encoding changes
+pub const INSTRUCTION_VERSION: u8 = 1;
+
+// variant order is the wire format, new variants go to the end
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 pub enum Instruction {
-    Deposit {
-        amount: u64,
-        pda_seed: [u8; 16],
-        bump_seed: u8
-    },
-    Withdraw {
-        amount: u64,
-        pda_seed: [u8; 16],
-        bump_seed: u8
-    },
-    ExchangeSolToToken { amount: u64 },
-    ExchangeTokenToSol { amount: u64 },
-    ExchangeTokenToToken { amount: u64 }
-}
-
-#[derive(BorshDeserialize)]
-pub struct InstructionData {
-    amount: u64,
-    pda_seed: String,
-    bump_seed: u8
+    Deposit(DepositArgs),
+    Withdraw(WithdrawArgs),
+    ExchangeSolToToken(ExchangeArgs),
+    ExchangeTokenToSol(ExchangeArgs),
+    ExchangeTokenToToken(ExchangeArgs),
+    InitializeExchange,
+    RegisterToken,
+    SetTokenEnabled(SetTokenEnabledArgs),
+    SetRole(SetRoleArgs),
+    RegisterPair(RegisterPairArgs),
+    SetCircuitBreaker(SetCircuitBreakerArgs),
+    Pause(PauseArgs),
+    Unpause(PauseArgs),
+    Migrate(MigrateArgs),
+    SetMaxPriceAge(SetMaxPriceAgeArgs),
+    SetOracle(SetOracleArgs),
+    SetMaxConfidence(SetMaxConfidenceArgs),
+    SetManualPrice(SetManualPriceArgs),
+    SetMaxManualPriceLifetime(SetMaxManualPriceLifetimeArgs),
+    SetPriceFeeds(SetPriceFeedsArgs),
+    UpdatePrice,
+    SetPairPricing(SetPairPricingArgs),
+    Swap(SwapArgs),
+    SwapExactOut(SwapExactOutArgs),
+    Quote(QuoteArgs),
+    SetFee(SetFeeArgs),
+    SetPairFee(SetPairFeeArgs),
+    InitializeFeeVault,
+    WithdrawFees(WithdrawFeesArgs),
+    SetPairSpread(SetPairSpreadArgs),
+    SetReferralShare(SetReferralShareArgs),
+    SetReferrer(SetReferrerArgs),
+    InitializeUserStats,
+    SetFeeTiers(SetFeeTiersArgs),
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum PauseScope {
+    Global,
+    Mint,
+    Pair,
+}
+
+// return data of the exchange instructions, the transaction succeeds either way
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum SwapResult {
+    // `fee` is in the output token, on top of `amount_out`
+    Executed { amount_in: u64, amount_out: u64, fee: u64 },
+    // the pair got paused instead and no tokens moved
+    CircuitBreakerTripped,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct DepositArgs {
+    pub amount: u64,
+    pub pda_seed: [u8; 16],
+    pub bump_seed: u8,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct WithdrawArgs {
+    pub amount: u64,
+}
+
+// payload of the legacy Exchange* variants, see SwapArgs
+#[derive(BorshSerialize, Clone, Debug, PartialEq)]
+pub struct ExchangeArgs {
+    pub amount: u64,
+    // 0 when an older client left it out
+    pub min_amount_out: u64,
+}
+
+impl BorshDeserialize for ExchangeArgs {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let amount = u64::deserialize_reader(reader)?;
+        let mut min_amount_out = [0; 8];
+        let mut filled = 0;
+        while filled < min_amount_out.len() {
+            match reader.read(&mut min_amount_out[filled..])? {
+                0 => break,
+                read => filled += read,
+            }
+        }
+        if filled != 0 && filled != min_amount_out.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated min_amount_out"));
+        }
+        Ok(ExchangeArgs { amount, min_amount_out: u64::from_le_bytes(min_amount_out) })
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetTokenEnabledArgs {
+    pub enabled: bool,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetRoleArgs {
+    pub role: Role,
+    pub authority: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct RegisterPairArgs {
+    pub max_price_change_bps: u16,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetCircuitBreakerArgs {
+    pub max_price_change_bps: u16,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct PauseArgs {
+    pub scope: PauseScope,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct MigrateArgs {
+    pub account_type: AccountType,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetMaxPriceAgeArgs {
+    pub max_price_age: u32,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetOracleArgs {
+    pub provider: OracleProvider,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetMaxConfidenceArgs {
+    pub max_confidence_bps: u16,
+}
+
+// `price * 10^exponent` USD per whole token
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetManualPriceArgs {
+    pub price: u64,
+    pub exponent: i32,
+    pub expires_at: i64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetMaxManualPriceLifetimeArgs {
+    pub max_manual_price_lifetime: u32,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetPriceFeedsArgs {
+    // provider of each passed feed account, the first one becomes the primary feed
+    pub providers: Vec<OracleProvider>,
+    pub min_valid_feeds: u8,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetPairPricingArgs {
+    pub price_mode: PriceMode,
+    pub twap_window: u32,
+    pub max_twap_deviation_bps: u16,
+}
+
+// A is what the client pays, B is what the client gets
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SwapArgs {
+    pub amount_in: u64,
+    pub min_amount_out: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SwapExactOutArgs {
+    pub amount_out: u64,
+    pub max_amount_in: u64,
+}
+
+// side of a swap the client fixed, with its limit on the other side
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum SwapMode {
+    ExactIn { amount_in: u64, min_amount_out: u64 },
+    ExactOut { amount_out: u64, max_amount_in: u64 },
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct QuoteArgs {
+    pub mode: SwapMode,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetFeeArgs {
+    pub fee_bps: u16,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetPairFeeArgs {
+    // None falls back to the exchange fee
+    pub fee_bps: Option<u16>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct WithdrawFeesArgs {
+    pub amount: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetPairSpreadArgs {
+    pub bid_spread_bps: u16,
+    pub ask_spread_bps: u16,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetReferralShareArgs {
+    pub referral_share_bps: u16,
+}
+
+// adds the wallet to the referrer allowlist or removes it
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetReferrerArgs {
+    pub referrer: Pubkey,
+    pub allowed: bool,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct SetFeeTiersArgs {
+    // replaces the whole table, by ascending min_volume_usd
+    pub tiers: Vec<FeeTier>,
+}
+
+// return data of Quote
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct QuoteResult {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    // part of the output kept by the exchange, in B tokens
+    pub fee: u64,
+    // one whole token A in token B the swap executes at, spread included and fee not,
+    // scaled by PAIR_PRICE_DECIMALS
+    pub price: u128,
 }
 
 impl Instruction {
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
-        let (&variant, rest) = input.split_first().ok_or(ProgramError::InvalidInstructionData)?;
-        let payload = InstructionData::try_from_slice(rest)?;
-        match variant {
-            0 => Ok( Self::Deposit {
-                amount: payload.amount,
-                pda_seed: <[u8; 16]>::try_from(payload.pda_seed.into_bytes()).unwrap(),
-                bump_seed: payload.bump_seed
+        let (&version, rest) = input.split_first().ok_or(ProgramError::InvalidInstructionData)?;
+        if version != INSTRUCTION_VERSION {
+            msg!("Unsupported instruction version {}", version);
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        Self::try_from_slice(rest).map_err(|_| {
+            msg!("Could not decode instruction data");
+            ProgramError::InvalidInstructionData
+        })
+    }
+
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec![INSTRUCTION_VERSION];
+        // writing into a Vec never fails
+        self.serialize(&mut data).unwrap();
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let instructions = [
+            Instruction::Deposit(DepositArgs { amount: 5, pda_seed: [7; 16], bump_seed: 254 }),
+            Instruction::Withdraw(WithdrawArgs { amount: u64::MAX }),
+            Instruction::ExchangeSolToToken(ExchangeArgs { amount: 1, min_amount_out: 2 }),
+            Instruction::InitializeExchange,
+            Instruction::SetTokenEnabled(SetTokenEnabledArgs { enabled: true }),
+            Instruction::SetRole(SetRoleArgs { role: Role::Pauser, authority: Pubkey::new_unique() }),
+            Instruction::RegisterPair(RegisterPairArgs { max_price_change_bps: 500 }),
+            Instruction::Pause(PauseArgs { scope: PauseScope::Pair }),
+            Instruction::Migrate(MigrateArgs { account_type: AccountType::PairConfig }),
+            Instruction::SetManualPrice(SetManualPriceArgs { price: 1, exponent: -8, expires_at: -1 }),
+            Instruction::SetMaxManualPriceLifetime(SetMaxManualPriceLifetimeArgs { max_manual_price_lifetime: 600 }),
+            Instruction::SetPriceFeeds(SetPriceFeedsArgs {
+                providers: vec![OracleProvider::Pyth, OracleProvider::Switchboard],
+                min_valid_feeds: 2,
+            }),
+            Instruction::UpdatePrice,
+            Instruction::SetPairPricing(SetPairPricingArgs {
+                price_mode: PriceMode::Twap,
+                twap_window: 600,
+                max_twap_deviation_bps: 50,
             }),
-            1 => Ok( Self::Withdraw {
-                amount: payload.amount,
-                pda_seed: <[u8; 16]>::try_from(payload.pda_seed.into_bytes()).unwrap(),
-                bump_seed: payload.bump_seed
+            Instruction::Swap(SwapArgs { amount_in: 10, min_amount_out: 9 }),
+            Instruction::SwapExactOut(SwapExactOutArgs { amount_out: 9, max_amount_in: 10 }),
+            Instruction::Quote(QuoteArgs { mode: SwapMode::ExactOut { amount_out: 9, max_amount_in: 10 } }),
+            Instruction::SetFee(SetFeeArgs { fee_bps: 30 }),
+            Instruction::SetPairFee(SetPairFeeArgs { fee_bps: None }),
+            Instruction::SetPairFee(SetPairFeeArgs { fee_bps: Some(0) }),
+            Instruction::InitializeFeeVault,
+            Instruction::WithdrawFees(WithdrawFeesArgs { amount: 7 }),
+            Instruction::SetPairSpread(SetPairSpreadArgs { bid_spread_bps: 25, ask_spread_bps: 1_000 }),
+            Instruction::SetReferralShare(SetReferralShareArgs { referral_share_bps: 2_000 }),
+            Instruction::SetReferrer(SetReferrerArgs { referrer: Pubkey::new_unique(), allowed: true }),
+            Instruction::InitializeUserStats,
+            Instruction::SetFeeTiers(SetFeeTiersArgs { tiers: Vec::new() }),
+            Instruction::SetFeeTiers(SetFeeTiersArgs {
+                tiers: vec![
+                    FeeTier { min_volume_usd: 10_000_000_000, fee_bps: 20 },
+                    FeeTier { min_volume_usd: u64::MAX, fee_bps: 0 },
+                ],
             }),
-            2 => Ok( Self::ExchangeSolToToken { amount: payload.amount } ),
-            3 => Ok( Self::ExchangeTokenToSol { amount: payload.amount } ),
-            4 => Ok( Self::ExchangeTokenToToken { amount: payload.amount }),
-            _ => Err(ProgramError::InvalidInstructionData)
+        ];
+        for instruction in instructions {
+            assert_eq!(Instruction::unpack(&instruction.pack()), Ok(instruction));
         }
     }
-}
 
+    #[test]
+    fn wire_layout() {
+        // clients encode these by hand, variant indexes must never move
+        assert_eq!(Instruction::InitializeExchange.pack(), [INSTRUCTION_VERSION, 5]);
+        assert_eq!(
+            Instruction::Migrate(MigrateArgs { account_type: AccountType::TokenConfig }).pack(),
+            [INSTRUCTION_VERSION, 13, 1]
+        );
+        assert_eq!(
+            Instruction::Withdraw(WithdrawArgs { amount: 0x0102 }).pack(),
+            [INSTRUCTION_VERSION, 1, 2, 1, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut data = Instruction::Withdraw(WithdrawArgs { amount: 10 }).pack();
+        for version in [0, INSTRUCTION_VERSION + 1, u8::MAX] {
+            data[0] = version;
+            assert_eq!(Instruction::unpack(&data), Err(ProgramError::InvalidInstructionData));
+        }
+        assert_eq!(Instruction::unpack(&[]), Err(ProgramError::InvalidInstructionData));
+    }
+
+    #[test]
+    fn rejects_unknown_variant_and_bad_payload() {
+        assert_eq!(Instruction::unpack(&[INSTRUCTION_VERSION, u8::MAX]), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(Instruction::unpack(&[INSTRUCTION_VERSION]), Err(ProgramError::InvalidInstructionData));
+
+        let data = Instruction::Withdraw(WithdrawArgs { amount: 5 }).pack();
+        assert_eq!(Instruction::unpack(&data[..data.len() - 1]), Err(ProgramError::InvalidInstructionData));
+        let mut trailing = data.clone();
+        trailing.push(0);
+        assert_eq!(Instruction::unpack(&trailing), Err(ProgramError::InvalidInstructionData));
+    }
 
+    #[test]
+    fn exchange_args_without_min_amount_out() {
+        let instruction = Instruction::ExchangeTokenToSol(ExchangeArgs { amount: 5, min_amount_out: 4 });
+        let data = instruction.pack();
+        assert_eq!(Instruction::unpack(&data), Ok(instruction));
 
+        // layout older clients send
+        let legacy = &data[..data.len() - 8];
+        assert_eq!(
+            Instruction::unpack(legacy),
+            Ok(Instruction::ExchangeTokenToSol(ExchangeArgs { amount: 5, min_amount_out: 0 }))
+        );
+        assert_eq!(Instruction::unpack(&data[..data.len() - 3]), Err(ProgramError::InvalidInstructionData));
+        let mut trailing = data.clone();
+        trailing.push(0);
+        assert_eq!(Instruction::unpack(&trailing), Err(ProgramError::InvalidInstructionData));
+    }
+}
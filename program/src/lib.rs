@@ -1,7 +1,16 @@
 extern crate core;
 
+#[cfg(not(feature = "no-entrypoint"))]
 mod entrypoint;
-mod processor;
-mod instruction;
-mod error;
+pub mod accounts;
+pub mod oracle;
+pub mod pricing;
+pub mod processor;
+pub mod pyth;
+pub mod switchboard;
+pub mod twap;
+pub mod volume;
+pub mod instruction;
+pub mod error;
+pub mod state;
 
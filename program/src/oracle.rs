@@ -0,0 +1,362 @@
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use chainlink_solana as chainlink;
+use crate::error::TokenError;
+use crate::pricing::{self, pow10, Price};
+use crate::pyth::PythPrice;
+use crate::switchboard::SwitchboardPrice;
+use crate::state::{ManualPrice, OracleProvider, TokenConfig};
+
+// A price as published by an oracle: `price * 10^exponent` USD per whole token.
+pub trait PriceSource {
+    fn price(&self) -> i128;
+    fn exponent(&self) -> i32;
+    // uncertainty of `price` in the same units, 0 if the provider does not publish one
+    fn confidence(&self) -> u128;
+    // unix timestamp
+    fn publish_time(&self) -> i64;
+}
+
+pub struct ChainlinkPrice {
+    round: chainlink::Round,
+    decimals: u8,
+}
+
+impl ChainlinkPrice {
+    pub fn read<'b>(
+        chainlink_program: &AccountInfo<'b>,
+        data_feed_account: &AccountInfo<'b>
+    ) -> Result<Self, ProgramError> {
+        if data_feed_account.owner != chainlink_program.key {
+            msg!("Data feed {} is not owned by Chainlink program", data_feed_account.key);
+            return Err(TokenError::MismatchedAccountsError.into());
+        }
+        let round = chainlink::latest_round_data(chainlink_program.clone(), data_feed_account.clone());
+        let decimals = chainlink::decimals(chainlink_program.clone(), data_feed_account.clone());
+        match (round, decimals) {
+            (Ok(round), Ok(decimals)) => Ok(ChainlinkPrice { round, decimals }),
+            _ => {
+                msg!("Could not read Chainlink feed {}", data_feed_account.key);
+                Err(TokenError::OracleDataFeedError.into())
+            }
+        }
+    }
+}
+
+impl PriceSource for ChainlinkPrice {
+    fn price(&self) -> i128 {
+        self.round.answer
+    }
+
+    fn exponent(&self) -> i32 {
+        -(self.decimals as i32)
+    }
+
+    fn confidence(&self) -> u128 {
+        0
+    }
+
+    fn publish_time(&self) -> i64 {
+        self.round.timestamp as i64
+    }
+}
+
+impl PriceSource for ManualPrice {
+    fn price(&self) -> i128 {
+        self.price as i128
+    }
+
+    fn exponent(&self) -> i32 {
+        self.exponent
+    }
+
+    fn confidence(&self) -> u128 {
+        0
+    }
+
+    fn publish_time(&self) -> i64 {
+        self.updated_at
+    }
+}
+
+// median price of the mint's feeds, `extra_feed_accounts` follow `TokenConfig::extra_feeds`;
+// feeds that fail to read or check are skipped as long as the quorum is met
+pub fn load_price<'b>(
+    program_id: &Pubkey,
+    token_config: &TokenConfig,
+    data_feed_account: &AccountInfo<'b>,
+    extra_feed_accounts: &[AccountInfo<'b>],
+    oracle_program: &AccountInfo<'b>,
+    now: i64
+) -> Result<Price, ProgramError> {
+    if extra_feed_accounts.len() != token_config.extra_feeds.len() {
+        msg!(
+            "Token {} has {} extra feeds, {} passed",
+            token_config.mint,
+            token_config.extra_feeds.len(),
+            extra_feed_accounts.len()
+        );
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let feed_accounts = std::iter::once(data_feed_account).chain(extra_feed_accounts);
+    let mut results = Vec::new();
+    for (feed, feed_account) in token_config.feeds().zip(feed_accounts) {
+        if feed.price_feed != *feed_account.key {
+            msg!("Data feed {} is not registered for token {}", feed_account.key, token_config.mint);
+            return Err(TokenError::OracleFeedMismatch.into());
+        }
+        let price = load_feed_price(program_id, token_config, feed.provider, feed_account, oracle_program, now);
+        if price.is_err() {
+            msg!("Skipping feed {}", feed_account.key);
+        }
+        results.push(price);
+    }
+    median_of_valid(results, token_config.min_valid_feeds())
+}
+
+// median of the feeds that returned a price, if there are at least `min_valid_feeds` of them
+pub fn median_of_valid(
+    results: Vec<Result<Price, ProgramError>>,
+    min_valid_feeds: usize
+) -> Result<Price, ProgramError> {
+    let feeds = results.len();
+    let mut prices = Vec::with_capacity(feeds);
+    let mut last_error = None;
+    for result in results {
+        match result {
+            Ok(price) => prices.push(price),
+            Err(error) => last_error = Some(error),
+        }
+    }
+    if prices.len() < min_valid_feeds.max(1) {
+        // a lone feed's own error says more than a missed quorum
+        if let (1, Some(error)) = (feeds, last_error) {
+            return Err(error);
+        }
+        msg!("{} of {} feeds are valid, {} required", prices.len(), feeds, min_valid_feeds);
+        return Err(TokenError::OracleQuorumNotMet.into());
+    }
+    pricing::median(&prices)
+}
+
+// price of a single feed from its provider
+fn load_feed_price<'b>(
+    program_id: &Pubkey,
+    token_config: &TokenConfig,
+    provider: OracleProvider,
+    data_feed_account: &AccountInfo<'b>,
+    oracle_program: &AccountInfo<'b>,
+    now: i64
+) -> Result<Price, ProgramError> {
+    let max_age = token_config.max_price_age();
+    let max_confidence_bps = token_config.max_confidence_bps();
+    match provider {
+        OracleProvider::Chainlink => checked_price(
+            &ChainlinkPrice::read(oracle_program, data_feed_account)?,
+            now,
+            max_age,
+            max_confidence_bps
+        ),
+        // feed addresses are pinned in the token config, readers check the program owning them
+        OracleProvider::Pyth => checked_price(
+            &PythPrice::read(data_feed_account)?,
+            now,
+            max_age,
+            max_confidence_bps
+        ),
+        OracleProvider::Switchboard => checked_price(
+            &SwitchboardPrice::read(data_feed_account)?,
+            now,
+            max_age,
+            max_confidence_bps
+        ),
+        OracleProvider::Manual => checked_manual_price(
+            &ManualPrice::load(program_id, data_feed_account, &token_config.mint)?,
+            now
+        ),
+    }
+}
+
+// manual prices can not be set to outlive the lifetime admin allows for the token
+pub fn check_manual_price_expiry(expires_at: i64, now: i64, max_lifetime: u32) -> ProgramResult {
+    if expires_at <= now {
+        msg!("Manual price expiry {} is not in the future", expires_at);
+        return Err(ProgramError::InvalidArgument);
+    }
+    if expires_at.saturating_sub(now) > max_lifetime as i64 {
+        msg!("Manual price may live at most {}s, expiry {} is too far ahead", max_lifetime, expires_at);
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+// expiry set by the operator takes the place of the max age
+pub fn checked_manual_price(manual_price: &ManualPrice, now: i64) -> Result<Price, ProgramError> {
+    if now > manual_price.expires_at {
+        msg!("Manual price of {} expired at {}", manual_price.mint, manual_price.expires_at);
+        return Err(TokenError::OracleStale.into());
+    }
+    checked_price(manual_price, now, u32::MAX, 0)
+}
+
+// fresh, positive and precise enough price of any source
+pub fn checked_price(
+    source: &impl PriceSource,
+    now: i64,
+    max_age: u32,
+    max_confidence_bps: u16
+) -> Result<Price, ProgramError> {
+    check_price_age(source.publish_time(), now, max_age)?;
+    if source.price() > 0 {
+        check_confidence(source.price() as u128, source.confidence(), max_confidence_bps)?;
+    }
+    let exponent = source.exponent();
+    if exponent > 0 {
+        let price = source.price()
+            .checked_mul(pow10(exponent as u32)? as i128)
+            .ok_or(TokenError::Overflow)?;
+        return Price::from_answer(price, 0);
+    }
+    let decimals = u8::try_from(-exponent).map_err(|_| TokenError::Overflow)?;
+    Price::from_answer(source.price(), decimals)
+}
+
+pub fn check_confidence(price: u128, confidence: u128, max_confidence_bps: u16) -> Result<(), ProgramError> {
+    let confidence_bps = confidence.checked_mul(10_000).ok_or(TokenError::Overflow)?;
+    let max_bps = price.checked_mul(max_confidence_bps as u128).ok_or(TokenError::Overflow)?;
+    if confidence_bps > max_bps {
+        msg!("Oracle confidence {} is wider than {} bps of price {}", confidence, max_confidence_bps, price);
+        return Err(TokenError::OracleConfidenceTooWide.into());
+    }
+    Ok(())
+}
+
+// seconds an oracle clock may run ahead of the cluster clock
+pub const MAX_CLOCK_SKEW: i64 = 5;
+
+// rejects prices published more than `max_age` seconds before `now`,
+// or further in the future than the clocks can disagree
+pub fn check_price_age(publish_time: i64, now: i64, max_age: u32) -> Result<(), ProgramError> {
+    if publish_time > now.saturating_add(MAX_CLOCK_SKEW) {
+        msg!("Oracle price is published {} seconds in the future", publish_time.saturating_sub(now));
+        return Err(TokenError::OracleStale.into());
+    }
+    let age = now.saturating_sub(publish_time);
+    if age > max_age as i64 {
+        msg!("Oracle price is {} seconds old, max allowed is {}", age, max_age);
+        return Err(TokenError::OracleStale.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedPrice {
+        price: i128,
+        exponent: i32,
+        publish_time: i64,
+    }
+
+    impl PriceSource for FixedPrice {
+        fn price(&self) -> i128 {
+            self.price
+        }
+
+        fn exponent(&self) -> i32 {
+            self.exponent
+        }
+
+        fn confidence(&self) -> u128 {
+            0
+        }
+
+        fn publish_time(&self) -> i64 {
+            self.publish_time
+        }
+    }
+
+    #[test]
+    fn price_age() {
+        assert_eq!(check_price_age(1_000, 1_000, 60), Ok(()));
+        assert_eq!(check_price_age(940, 1_000, 60), Ok(()));
+        assert_eq!(check_price_age(939, 1_000, 60), Err(TokenError::OracleStale.into()));
+        assert_eq!(check_price_age(0, 1_000, 0), Err(TokenError::OracleStale.into()));
+        // clock lagging a little behind the oracle is tolerated, a price from the future is not
+        assert_eq!(check_price_age(1_000 + MAX_CLOCK_SKEW, 1_000, 0), Ok(()));
+        assert_eq!(check_price_age(1_001 + MAX_CLOCK_SKEW, 1_000, 60), Err(TokenError::OracleStale.into()));
+        assert_eq!(check_price_age(i64::MAX, 1_000, u32::MAX), Err(TokenError::OracleStale.into()));
+    }
+
+    #[test]
+    fn checked_price_exponents() {
+        let source = FixedPrice { price: 2_012_345_678, exponent: -8, publish_time: 100 };
+        assert_eq!(checked_price(&source, 100, 60, 100), Ok(Price { value: 2_012_345_678, decimals: 8 }));
+
+        let source = FixedPrice { price: 25, exponent: 3, publish_time: 100 };
+        assert_eq!(checked_price(&source, 100, 60, 100), Ok(Price { value: 25_000, decimals: 0 }));
+
+        let source = FixedPrice { price: 1, exponent: -300, publish_time: 100 };
+        assert_eq!(checked_price(&source, 100, 60, 100), Err(TokenError::Overflow.into()));
+    }
+
+    #[test]
+    fn checked_price_rejects_stale_and_invalid() {
+        let source = FixedPrice { price: 100, exponent: -2, publish_time: 0 };
+        assert_eq!(checked_price(&source, 61, 60, 100), Err(TokenError::OracleStale.into()));
+
+        let source = FixedPrice { price: -100, exponent: -2, publish_time: 0 };
+        assert_eq!(checked_price(&source, 0, 60, 100), Err(TokenError::OracleInvalidPrice.into()));
+    }
+
+    #[test]
+    fn manual_price_expires() {
+        let manual_price = ManualPrice {
+            is_initialized: true,
+            price: 125,
+            exponent: -2,
+            updated_at: 1_000,
+            expires_at: 100_000,
+            ..ManualPrice::default()
+        };
+        // any age is fine until expiry
+        assert_eq!(checked_manual_price(&manual_price, 100_000), Ok(Price { value: 125, decimals: 2 }));
+        assert_eq!(checked_manual_price(&manual_price, 100_001), Err(TokenError::OracleStale.into()));
+
+        let manual_price = ManualPrice { price: 0, ..manual_price };
+        assert_eq!(checked_manual_price(&manual_price, 1_000), Err(TokenError::OracleInvalidPrice.into()));
+    }
+
+    #[test]
+    fn manual_price_lifetime_is_capped() {
+        assert_eq!(check_manual_price_expiry(1_001, 1_000, 60), Ok(()));
+        assert_eq!(check_manual_price_expiry(1_060, 1_000, 60), Ok(()));
+        assert_eq!(check_manual_price_expiry(1_061, 1_000, 60), Err(ProgramError::InvalidArgument));
+        assert_eq!(check_manual_price_expiry(i64::MAX, 1_000, u32::MAX), Err(ProgramError::InvalidArgument));
+        assert_eq!(check_manual_price_expiry(1_000, 1_000, 60), Err(ProgramError::InvalidArgument));
+        assert_eq!(check_manual_price_expiry(i64::MIN, 1_000, 60), Err(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn median_of_valid_feeds() {
+        let price = |value| Ok(Price { value, decimals: 2 });
+        let stale = || Err(TokenError::OracleStale.into());
+
+        assert_eq!(median_of_valid(vec![price(100)], 1), price(100));
+        assert_eq!(median_of_valid(vec![price(100), stale(), price(300), price(200)], 3), price(200));
+        assert_eq!(median_of_valid(vec![price(100), stale(), price(300)], 2), price(200));
+        assert_eq!(
+            median_of_valid(vec![price(100), stale(), stale()], 2),
+            Err(TokenError::OracleQuorumNotMet.into())
+        );
+        assert_eq!(median_of_valid(vec![stale(), stale()], 0), Err(TokenError::OracleQuorumNotMet.into()));
+        // single feed keeps its own error
+        assert_eq!(median_of_valid(vec![stale()], 1), stale());
+    }
+}
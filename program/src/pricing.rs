@@ -0,0 +1,518 @@
+use std::fmt;
+use solana_program::msg;
+use solana_program::program_error::ProgramError;
+use crate::error::TokenError;
+use crate::instruction::SwapMode;
+use crate::state::{PAIR_PRICE_DECIMALS, USD_VOLUME_DECIMALS};
+
+// Integer pricing used by the swaps. Prices are kept as the oracle reported them
+// (value / 10^decimals), amounts are raw token units, nothing goes through floats.
+// Every conversion takes its rounding direction explicitly: amounts paid out by the
+// exchange round down, amounts charged to the client round up.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+// USD price of one whole token
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Price {
+    pub value: u128,
+    pub decimals: u8,
+}
+
+impl Price {
+    pub fn from_answer(answer: i128, decimals: u8) -> Result<Self, ProgramError> {
+        if answer <= 0 {
+            msg!("Oracle answer {} is not a valid price", answer);
+            return Err(TokenError::OracleInvalidPrice.into());
+        }
+        Ok(Price { value: answer as u128, decimals })
+    }
+
+    // value at other decimals, rounded down
+    pub fn rescale(&self, decimals: u8) -> Result<u128, ProgramError> {
+        if decimals >= self.decimals {
+            self.value
+                .checked_mul(pow10((decimals - self.decimals) as u32)?)
+                .ok_or_else(|| TokenError::Overflow.into())
+        } else {
+            Ok(pow10((self.decimals - decimals) as u32).map_or(0, |divisor| self.value / divisor))
+        }
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.decimals == 0 {
+            return write!(f, "{}", self.value);
+        }
+        match 10_u128.checked_pow(self.decimals as u32) {
+            Some(one) => write!(
+                f,
+                "{}.{:0width$}",
+                self.value / one,
+                self.value % one,
+                width = self.decimals as usize
+            ),
+            None => write!(f, "{}e-{}", self.value, self.decimals),
+        }
+    }
+}
+
+pub fn pow10(exponent: u32) -> Result<u128, ProgramError> {
+    10_u128.checked_pow(exponent).ok_or_else(|| TokenError::Overflow.into())
+}
+
+// a * b / c
+pub fn mul_div(a: u128, b: u128, c: u128, rounding: Rounding) -> Result<u128, ProgramError> {
+    if c == 0 {
+        return Err(TokenError::Overflow.into());
+    }
+    let product = a.checked_mul(b).ok_or(TokenError::Overflow)?;
+    let quotient = product / c;
+    match rounding {
+        Rounding::Up if product % c != 0 => {
+            quotient.checked_add(1).ok_or_else(|| TokenError::Overflow.into())
+        }
+        _ => Ok(quotient),
+    }
+}
+
+// value * 10^num_exponent / (divisor * 10^den_exponent), only the difference of
+// the exponents is ever materialized
+fn scale_div(
+    value: u128,
+    num_exponent: u32,
+    divisor: u128,
+    den_exponent: u32,
+    rounding: Rounding
+) -> Result<u128, ProgramError> {
+    if num_exponent >= den_exponent {
+        mul_div(value, pow10(num_exponent - den_exponent)?, divisor, rounding)
+    } else {
+        let divisor = divisor
+            .checked_mul(pow10(den_exponent - num_exponent)?)
+            .ok_or(TokenError::Overflow)?;
+        mul_div(value, 1, divisor, rounding)
+    }
+}
+
+// amount of the output token worth `amount_in` of the input token
+pub fn convert(
+    amount_in: u64,
+    decimals_in: u8,
+    price_in: &Price,
+    decimals_out: u8,
+    price_out: &Price,
+    rounding: Rounding
+) -> Result<u64, ProgramError> {
+    let value_in = (amount_in as u128)
+        .checked_mul(price_in.value)
+        .ok_or(TokenError::Overflow)?;
+    let amount_out = scale_div(
+        value_in,
+        price_out.decimals as u32 + decimals_out as u32,
+        price_out.value,
+        price_in.decimals as u32 + decimals_in as u32,
+        rounding
+    )?;
+    u64::try_from(amount_out).map_err(|_| TokenError::Overflow.into())
+}
+
+// USD worth of `amount`, scaled by USD_VOLUME_DECIMALS and rounded down
+pub fn usd_value(amount: u64, decimals: u8, price: &Price) -> Result<u64, ProgramError> {
+    let value = (amount as u128)
+        .checked_mul(price.value)
+        .ok_or(TokenError::Overflow)?;
+    let value = scale_div(
+        value,
+        USD_VOLUME_DECIMALS as u32,
+        1,
+        price.decimals as u32 + decimals as u32,
+        Rounding::Down
+    )?;
+    u64::try_from(value).map_err(|_| TokenError::Overflow.into())
+}
+
+// median of prices with any decimals, at the largest decimals among them;
+// an even count averages the two middle prices, rounding down
+pub fn median(prices: &[Price]) -> Result<Price, ProgramError> {
+    let decimals = match prices.iter().map(|price| price.decimals).max() {
+        Some(decimals) => decimals,
+        None => return Err(TokenError::OracleDataFeedError.into()),
+    };
+    let mut values = prices
+        .iter()
+        .map(|price| price.rescale(decimals))
+        .collect::<Result<Vec<u128>, ProgramError>>()?;
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    let value = if values.len() % 2 == 1 {
+        values[middle]
+    } else {
+        // no overflow: each half fits and the remainders add up to at most one
+        values[middle - 1] / 2 + values[middle] / 2 + (values[middle - 1] % 2 + values[middle] % 2) / 2
+    };
+    Ok(Price { value, decimals })
+}
+
+// part of `amount` kept as fee, rounded up
+pub fn fee_of(amount: u64, fee_bps: u16) -> Result<u64, ProgramError> {
+    let fee = mul_div(amount as u128, fee_bps as u128, 10_000, Rounding::Up)?;
+    u64::try_from(fee).map_err(|_| TokenError::Overflow.into())
+}
+
+// part of a collected fee the referrer gets, rounded down
+pub fn referral_share(fee: u64, share_bps: u16) -> Result<u64, ProgramError> {
+    let share = mul_div(fee as u128, share_bps as u128, 10_000, Rounding::Down)?;
+    u64::try_from(share).map_err(|_| TokenError::Overflow.into())
+}
+
+// smallest amount that still leaves `net` once its fee is taken, rounded up
+pub fn amount_before_fee(net: u64, fee_bps: u16) -> Result<u64, ProgramError> {
+    let kept_bps = 10_000u128.checked_sub(fee_bps as u128).ok_or(TokenError::Overflow)?;
+    let amount = mul_div(net as u128, 10_000, kept_bps, Rounding::Up)?;
+    u64::try_from(amount).map_err(|_| TokenError::Overflow.into())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SwapAmounts {
+    pub amount_in: u64,
+    // what the client gets, after the fee
+    pub amount_out: u64,
+    // in the output token
+    pub fee: u64,
+}
+
+// amounts paid and received, the side the client did not fix is rounded against the client.
+// the fee is taken from the output: ExactIn pays less, ExactOut charges for the fee as well
+pub fn swap_amounts(
+    mode: SwapMode,
+    decimals_in: u8,
+    price_in: &Price,
+    decimals_out: u8,
+    price_out: &Price,
+    fee_bps: u16
+) -> Result<SwapAmounts, ProgramError> {
+    match mode {
+        SwapMode::ExactIn { amount_in, min_amount_out } => {
+            let gross_amount_out = convert(amount_in, decimals_in, price_in, decimals_out, price_out, Rounding::Down)?;
+            let fee = fee_of(gross_amount_out, fee_bps)?;
+            let amount_out = gross_amount_out - fee;
+            if amount_out < min_amount_out {
+                msg!("Swap would pay {}, client asked for at least {}", amount_out, min_amount_out);
+                return Err(TokenError::SlippageExceeded.into());
+            }
+            Ok(SwapAmounts { amount_in, amount_out, fee })
+        }
+        SwapMode::ExactOut { amount_out, max_amount_in } => {
+            let gross_amount_out = amount_before_fee(amount_out, fee_bps)?;
+            let amount_in = convert(gross_amount_out, decimals_out, price_out, decimals_in, price_in, Rounding::Up)?;
+            if amount_in > max_amount_in {
+                msg!("Swap would charge {}, client allowed at most {}", amount_in, max_amount_in);
+                return Err(TokenError::SlippageExceeded.into());
+            }
+            Ok(SwapAmounts { amount_in, amount_out, fee: gross_amount_out - amount_out })
+        }
+    }
+}
+
+// `spread_bps` below `mid`, kept exact by adding four decimals
+pub fn bid_price(mid: &Price, spread_bps: u16) -> Result<Price, ProgramError> {
+    let factor = 10_000u128.checked_sub(spread_bps as u128).ok_or(TokenError::Overflow)?;
+    with_factor_bps(mid, factor)
+}
+
+// `spread_bps` above `mid`, kept exact by adding four decimals
+pub fn ask_price(mid: &Price, spread_bps: u16) -> Result<Price, ProgramError> {
+    with_factor_bps(mid, 10_000 + spread_bps as u128)
+}
+
+fn with_factor_bps(price: &Price, factor_bps: u128) -> Result<Price, ProgramError> {
+    Ok(Price {
+        value: price.value.checked_mul(factor_bps).ok_or(TokenError::Overflow)?,
+        decimals: price.decimals.checked_add(4).ok_or(TokenError::Overflow)?,
+    })
+}
+
+// price of `base` in `quote` tokens, scaled by PAIR_PRICE_DECIMALS
+pub fn pair_price(base: &Price, quote: &Price) -> Result<u128, ProgramError> {
+    scale_div(
+        base.value,
+        PAIR_PRICE_DECIMALS + quote.decimals as u32,
+        quote.value,
+        base.decimals as u32,
+        Rounding::Down
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOL: Price = Price { value: 2_012_345_678, decimals: 8 };
+    const USDC: Price = Price { value: 100_000_000, decimals: 8 };
+
+    fn overflow() -> ProgramError {
+        TokenError::Overflow.into()
+    }
+
+    #[test]
+    fn price_from_answer() {
+        assert_eq!(Price::from_answer(2_012_345_678, 8), Ok(SOL));
+        assert_eq!(Price::from_answer(0, 8), Err(TokenError::OracleInvalidPrice.into()));
+        assert_eq!(Price::from_answer(-1, 8), Err(TokenError::OracleInvalidPrice.into()));
+    }
+
+    #[test]
+    fn price_display() {
+        assert_eq!(SOL.to_string(), "20.12345678");
+        assert_eq!(Price { value: 5, decimals: 0 }.to_string(), "5");
+        assert_eq!(Price { value: 5, decimals: 3 }.to_string(), "0.005");
+    }
+
+    #[test]
+    fn mul_div_rounding() {
+        let cases: &[(u128, u128, u128, u128, u128)] = &[
+            // a, b, c, down, up
+            (10, 10, 5, 20, 20),
+            (10, 10, 3, 33, 34),
+            (1, 1, 2, 0, 1),
+            (0, 7, 3, 0, 0),
+            (u64::MAX as u128, u64::MAX as u128, u64::MAX as u128, u64::MAX as u128, u64::MAX as u128),
+            (u128::MAX, 1, 2, u128::MAX / 2, u128::MAX / 2 + 1),
+        ];
+        for &(a, b, c, down, up) in cases {
+            assert_eq!(mul_div(a, b, c, Rounding::Down), Ok(down), "{} * {} / {}", a, b, c);
+            assert_eq!(mul_div(a, b, c, Rounding::Up), Ok(up), "{} * {} / {}", a, b, c);
+        }
+    }
+
+    #[test]
+    fn mul_div_overflow() {
+        assert_eq!(mul_div(u128::MAX, 2, 2, Rounding::Down), Err(overflow()));
+        assert_eq!(mul_div(1, 1, 0, Rounding::Down), Err(overflow()));
+        assert_eq!(mul_div(u128::MAX, 1, u128::MAX - 1, Rounding::Up), Ok(2));
+    }
+
+    #[test]
+    fn convert_reference_values() {
+        let cases: &[(u64, u8, Price, u8, Price, u64, u64)] = &[
+            // amount in, decimals in, price in, decimals out, price out, down, up
+            // 1 SOL at 20.12345678 to USDC, 20.12345678 USDC truncated to 6 decimals
+            (1_000_000_000, 9, SOL, 6, USDC, 20_123_456, 20_123_457),
+            // 20.123456 USDC back to SOL, 0.9999999612... SOL
+            (20_123_456, 6, USDC, 9, SOL, 999_999_961, 999_999_962),
+            // exact conversion, no rounding
+            (20_000_000, 6, USDC, 9, Price { value: 20, decimals: 0 }, 1_000_000_000, 1_000_000_000),
+            // feeds with different decimals: 3 tokens at 2.0 for tokens at 0.5
+            (3, 0, Price { value: 2 * 10_u128.pow(18), decimals: 18 }, 0, Price { value: 50_000_000, decimals: 8 }, 12, 12),
+            // one lamport is worth less than the smallest USDC unit
+            (1, 9, USDC, 6, USDC, 0, 1),
+            // same token, different decimals
+            (123_456_789, 9, USDC, 6, USDC, 123_456, 123_457),
+            (123_456, 6, USDC, 9, USDC, 123_456_000, 123_456_000),
+            (0, 9, SOL, 6, USDC, 0, 0),
+        ];
+        for &(amount, decimals_in, price_in, decimals_out, price_out, down, up) in cases {
+            assert_eq!(
+                convert(amount, decimals_in, &price_in, decimals_out, &price_out, Rounding::Down),
+                Ok(down),
+                "{} at {} -> {}", amount, price_in, price_out
+            );
+            assert_eq!(
+                convert(amount, decimals_in, &price_in, decimals_out, &price_out, Rounding::Up),
+                Ok(up),
+                "{} at {} -> {}", amount, price_in, price_out
+            );
+        }
+    }
+
+    #[test]
+    fn convert_overflow() {
+        let two = Price { value: 2, decimals: 0 };
+        let one = Price { value: 1, decimals: 0 };
+        // result does not fit into u64
+        assert_eq!(convert(u64::MAX, 0, &two, 0, &one, Rounding::Down), Err(overflow()));
+        // intermediate value does not fit into u128
+        let huge = Price { value: u128::MAX / 2, decimals: 0 };
+        assert_eq!(convert(4, 0, &huge, 0, &one, Rounding::Down), Err(overflow()));
+        // scale factor does not fit into u128
+        assert_eq!(convert(1, 0, &one, 40, &one, Rounding::Down), Err(overflow()));
+        assert_eq!(convert(1, 40, &one, 0, &one, Rounding::Down), Err(overflow()));
+    }
+
+    #[test]
+    fn round_trip_never_gains() {
+        for &amount in &[1, 7, 999, 1_000_000, 123_456_789, 1_000_000_000_000] {
+            let usdc = convert(amount, 9, &SOL, 6, &USDC, Rounding::Down).unwrap();
+            let back = convert(usdc, 6, &USDC, 9, &SOL, Rounding::Down).unwrap();
+            assert!(back <= amount, "{} -> {} -> {}", amount, usdc, back);
+
+            // charging with rounding up always covers the requested output
+            let cost = convert(usdc, 6, &USDC, 9, &SOL, Rounding::Up).unwrap();
+            assert!(convert(cost, 9, &SOL, 6, &USDC, Rounding::Down).unwrap() >= usdc);
+        }
+    }
+
+    #[test]
+    fn pair_price_reference_values() {
+        assert_eq!(pair_price(&SOL, &USDC), Ok(20_123_456_780));
+        assert_eq!(pair_price(&USDC, &SOL), Ok(49_693_251));
+        assert_eq!(
+            pair_price(&Price { value: 2 * 10_u128.pow(18), decimals: 18 }, &Price { value: 50_000_000, decimals: 8 }),
+            Ok(4_000_000_000)
+        );
+        assert_eq!(pair_price(&SOL, &Price { value: 0, decimals: 8 }), Err(overflow()));
+    }
+
+    #[test]
+    fn price_rescale() {
+        assert_eq!(SOL.rescale(8), Ok(2_012_345_678));
+        assert_eq!(SOL.rescale(9), Ok(20_123_456_780));
+        assert_eq!(SOL.rescale(2), Ok(2_012));
+        assert_eq!(SOL.rescale(0), Ok(20));
+        assert_eq!(Price { value: 1, decimals: 255 }.rescale(0), Ok(0));
+        assert_eq!(SOL.rescale(255), Err(overflow()));
+    }
+
+    #[test]
+    fn median_of_prices() {
+        let price = |value, decimals| Price { value, decimals };
+        assert_eq!(median(&[SOL]), Ok(SOL));
+        assert_eq!(median(&[price(30, 0), price(10, 0), price(20, 0)]), Ok(price(20, 0)));
+        // even count averages the middle two, rounding down
+        assert_eq!(median(&[price(10, 0), price(21, 0), price(40, 0), price(1, 0)]), Ok(price(15, 0)));
+        assert_eq!(median(&[price(u128::MAX, 0), price(u128::MAX, 0)]), Ok(price(u128::MAX, 0)));
+        // rescaled to the largest decimals
+        assert_eq!(
+            median(&[price(2_012, 2), price(2_013_000, 5), price(201_400_000, 7)]),
+            Ok(price(201_300_000, 7))
+        );
+        assert_eq!(median(&[price(u128::MAX, 0), price(1, 1)]), Err(overflow()));
+        assert_eq!(median(&[]), Err(TokenError::OracleDataFeedError.into()));
+    }
+
+    #[test]
+    fn swap_amounts_reference_values() {
+        let exact_in = |amount_in, min_amount_out, fee_bps| {
+            swap_amounts(SwapMode::ExactIn { amount_in, min_amount_out }, 9, &SOL, 6, &USDC, fee_bps)
+        };
+        let exact_out = |amount_out, max_amount_in, fee_bps| {
+            swap_amounts(SwapMode::ExactOut { amount_out, max_amount_in }, 9, &SOL, 6, &USDC, fee_bps)
+        };
+        // 1 SOL pays 20.123456 USDC, 30 bps fee rounded up
+        assert_eq!(
+            exact_in(1_000_000_000, 0, 30),
+            Ok(SwapAmounts { amount_in: 1_000_000_000, amount_out: 20_063_085, fee: 60_371 })
+        );
+        assert_eq!(
+            exact_in(1_000_000_000, 20_123_456, 0),
+            Ok(SwapAmounts { amount_in: 1_000_000_000, amount_out: 20_123_456, fee: 0 })
+        );
+        assert_eq!(exact_in(1_000_000_000, 20_063_086, 30), Err(TokenError::SlippageExceeded.into()));
+
+        // 20.063085 USDC take 20.123456 before the fee, 0.99999996... SOL rounded up
+        assert_eq!(
+            exact_out(20_063_085, u64::MAX, 30),
+            Ok(SwapAmounts { amount_in: 999_999_962, amount_out: 20_063_085, fee: 60_371 })
+        );
+        assert_eq!(
+            exact_out(20_000_000, 1_000_000_000, 0),
+            Ok(SwapAmounts { amount_in: 993_865_032, amount_out: 20_000_000, fee: 0 })
+        );
+        assert_eq!(exact_out(20_063_085, 999_999_961, 30), Err(TokenError::SlippageExceeded.into()));
+        assert_eq!(exact_out(1, 0, 10_000), Err(overflow()));
+        assert_eq!(exact_out(u64::MAX, u64::MAX, 0), Err(overflow()));
+    }
+
+    #[test]
+    fn exact_out_charges_enough_within_limit() {
+        for amount_out in [1, 7, 999, 1_000_000, 20_123_456, 123_456_789] {
+            for fee_bps in [0, 1, 30, 1_000] {
+                let mode = SwapMode::ExactOut { amount_out, max_amount_in: u64::MAX };
+                let amounts = swap_amounts(mode, 9, &SOL, 6, &USDC, fee_bps).unwrap();
+                assert_eq!(amounts.amount_out, amount_out);
+
+                // rounded up: the input pays for the output and its fee, one unit less does not
+                let gross_amount_out = amount_out + amounts.fee;
+                let paid_for = convert(amounts.amount_in, 9, &SOL, 6, &USDC, Rounding::Down).unwrap();
+                assert!(paid_for >= gross_amount_out, "{} at {} bps", amount_out, fee_bps);
+                let short = convert(amounts.amount_in - 1, 9, &SOL, 6, &USDC, Rounding::Down).unwrap();
+                assert!(short < gross_amount_out, "{} at {} bps", amount_out, fee_bps);
+
+                // the exact charge is within the limit, one unit below it is not
+                let mode = SwapMode::ExactOut { amount_out, max_amount_in: amounts.amount_in };
+                assert_eq!(swap_amounts(mode, 9, &SOL, 6, &USDC, fee_bps), Ok(amounts));
+                let mode = SwapMode::ExactOut { amount_out, max_amount_in: amounts.amount_in - 1 };
+                assert_eq!(swap_amounts(mode, 9, &SOL, 6, &USDC, fee_bps), Err(TokenError::SlippageExceeded.into()));
+            }
+        }
+    }
+
+    #[test]
+    fn exact_in_and_exact_out_agree() {
+        for amount_in in [1_000, 999_999_962, 1_000_000_000, 123_456_789_000] {
+            for fee_bps in [0, 1, 30, 1_000] {
+                let mode = SwapMode::ExactIn { amount_in, min_amount_out: 0 };
+                let exact_in = swap_amounts(mode, 9, &SOL, 6, &USDC, fee_bps).unwrap();
+                // asking for what ExactIn pays never charges more than it took
+                let mode = SwapMode::ExactOut { amount_out: exact_in.amount_out, max_amount_in: amount_in };
+                let exact_out = swap_amounts(mode, 9, &SOL, 6, &USDC, fee_bps).unwrap();
+                assert!(exact_out.amount_in <= amount_in, "{} at {} bps", amount_in, fee_bps);
+                // and paying what ExactOut charged gets at least the requested output
+                let mode = SwapMode::ExactIn { amount_in: exact_out.amount_in, min_amount_out: exact_out.amount_out };
+                assert!(swap_amounts(mode, 9, &SOL, 6, &USDC, fee_bps).is_ok(), "{} at {} bps", amount_in, fee_bps);
+            }
+        }
+    }
+
+    #[test]
+    fn usd_value_of_amount() {
+        // 1.5 SOL at 20.12345678
+        assert_eq!(usd_value(1_500_000_000, 9, &SOL), Ok(30_185_185));
+        assert_eq!(usd_value(20_123_456, 6, &USDC), Ok(20_123_456));
+        assert_eq!(usd_value(1, 9, &SOL), Ok(0));
+        assert_eq!(usd_value(u64::MAX, 0, &USDC), Err(overflow()));
+    }
+
+    #[test]
+    fn spread_around_mid() {
+        assert_eq!(bid_price(&SOL, 25), Ok(Price { value: 2_012_345_678 * 9_975, decimals: 12 }));
+        assert_eq!(ask_price(&SOL, 25), Ok(Price { value: 2_012_345_678 * 10_025, decimals: 12 }));
+        assert_eq!(bid_price(&SOL, 0).unwrap().rescale(8), Ok(SOL.value));
+        // 1 SOL sold at 0.25% below 20.12345678, 20.073148138... USDC
+        let bid = bid_price(&SOL, 25).unwrap();
+        assert_eq!(convert(1_000_000_000, 9, &bid, 6, &USDC, Rounding::Down), Ok(20_073_148));
+        assert_eq!(bid_price(&SOL, 10_001), Err(overflow()));
+        assert_eq!(ask_price(&Price { value: u128::MAX, decimals: 0 }, 1), Err(overflow()));
+        assert_eq!(ask_price(&Price { value: 1, decimals: 253 }, 1), Err(overflow()));
+    }
+
+    #[test]
+    fn fee_rounds_against_client() {
+        assert_eq!(fee_of(1_000_000, 30), Ok(3_000));
+        assert_eq!(fee_of(1_001, 30), Ok(4));
+        assert_eq!(fee_of(1_000_000, 0), Ok(0));
+        assert_eq!(fee_of(u64::MAX, 10_000), Ok(u64::MAX));
+
+        assert_eq!(amount_before_fee(997_000, 30), Ok(1_000_000));
+        assert_eq!(amount_before_fee(1_000, 30), Ok(1_004));
+        assert_eq!(amount_before_fee(1_000, 0), Ok(1_000));
+        assert_eq!(amount_before_fee(1, 10_000), Err(overflow()));
+        assert_eq!(amount_before_fee(u64::MAX, 30), Err(overflow()));
+        assert_eq!(referral_share(3_000, 2_000), Ok(600));
+        assert_eq!(referral_share(4, 2_000), Ok(0));
+        assert_eq!(referral_share(u64::MAX, 10_000), Ok(u64::MAX));
+        // what is left after the fee always covers the requested amount
+        for net in [1, 7, 999, 1_000, 123_456_789] {
+            for fee_bps in [1, 30, 999, 1_000] {
+                let amount = amount_before_fee(net, fee_bps).unwrap();
+                assert!(amount - fee_of(amount, fee_bps).unwrap() >= net, "{} at {} bps", net, fee_bps);
+            }
+        }
+    }
+}
@@ -1,10 +1,8 @@
-use std::collections::HashSet;
 use solana_program::
 {
-    account_info::{next_account_info, AccountInfo},
+    account_info::AccountInfo,
     entrypoint::ProgramResult,
     msg,
-    native_token::LAMPORTS_PER_SOL,
     program::invoke,
     program_error::ProgramError,
     pubkey::Pubkey,
@@ -14,57 +12,355 @@ use spl_token::{
     self,
     instruction as token_instruction,
 };
-use chainlink_solana as chainlink;
-use num_traits::{FromPrimitive, Pow, ToPrimitive};
-use solana_program::program::invoke_signed;
+use borsh::BorshSerialize;
+use num_traits::ToPrimitive;
+use solana_program::program::{invoke_signed, set_return_data};
+use solana_program::program_pack::Pack;
+use solana_program::clock::Clock;
 use solana_program::rent::Rent;
 use solana_program::sysvar::Sysvar;
+use crate::accounts::{
+    DepositAccounts, InitializeExchangeAccounts, InitializeFeeVaultAccounts, InitializeUserStatsAccounts,
+    MigrateAccounts, PauseAccounts, PauseTargetAccounts, QuoteAccounts, RegisterPairAccounts, RegisterTokenAccounts, SetManualPriceAccounts,
+    SetOracleAccounts, SetPriceFeedsAccounts, SwapAccounts, UpdateExchangeStateAccounts, UpdatePairConfigAccounts,
+    UpdatePriceAccounts, UpdateTokenConfigAccounts, WithdrawAccounts, WithdrawFeesAccounts
+};
 use crate::error::TokenError;
-use crate::instruction::Instruction;
+use crate::instruction::{Instruction, PauseScope, QuoteResult, SwapMode, SwapResult};
+use crate::oracle;
+use crate::pricing::{self, Price};
+use crate::state::{
+    AccountType, ExchangeState, FeeTier, ManualPrice, OracleFeed, OracleProvider, PairConfig, PriceHistory, PriceMode,
+    RegisteredMint, Role, StateAccount, TokenConfig, UserStats, EXCHANGE_STATE_SEED, FEE_VAULT_SEED, MANUAL_PRICE_SEED,
+    MAX_FEE_BPS, MAX_FEE_TIERS, MAX_MINTS, MAX_PRICE_OBSERVATIONS, MAX_REFERRERS, MAX_SPREAD_BPS, PAIR_CONFIG_SEED, PRICE_HISTORY_SEED,
+    PRICE_OBSERVATION_INTERVAL, TOKEN_CONFIG_SEED, USER_STATS_SEED
+};
+use crate::twap;
+use crate::volume;
 
-pub(crate) struct Processor {
-    pub(crate) token_mints: HashSet<String>  // token mint ID's
+pub struct Processor<'a> {
+    exchange_state_account: &'a AccountInfo<'a>,
+    exchange_state: ExchangeState,
 }
 
-impl Processor {
+impl<'a> Processor<'a> {
     // TOKENS ARE ALWAYS TRANSFERRED :
     // FROM A TO A (FROM CLIENT TO EXCHANGE)
     // FROM B TO B (FROM EXCHANGE TO CLIENT)
-    #[allow(non_snake_case)]
-    pub fn process_instruction<'a>(
-        &mut self,
+    pub fn process_instruction(
         program_id: &'a Pubkey,
         accounts: &'a [AccountInfo<'a>],
         instruction_data: &'a [u8],
     ) -> ProgramResult {
         let instruction = Instruction::unpack(instruction_data)?;
+        match instruction {
+            Instruction::InitializeExchange => {
+                return Self::initialize_exchange(program_id, accounts);
+            }
+            // exchange state itself may still have an outdated layout
+            Instruction::Migrate(args) => {
+                return Self::migrate(program_id, accounts, args.account_type);
+            }
+            _ => {}
+        }
+
+        // exchange state account is always the first one
+        let (exchange_state_account, accounts) = accounts
+            .split_first()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let exchange_state = ExchangeState::load(program_id, exchange_state_account)?;
+        let mut processor = Processor { exchange_state_account, exchange_state };
 
         match instruction {
-            Instruction::Deposit { amount, pda_seed, bump_seed } => {
-                Self::deposit(self, program_id, accounts, amount, &pda_seed, bump_seed)?;
+            Instruction::Deposit(args) => {
+                processor.deposit(program_id, accounts, args.amount, &args.pda_seed, args.bump_seed)?;
+                Ok(())
+            }
+
+            Instruction::Withdraw(args) => {
+                processor.withdraw(accounts, args.amount)?;
+                Ok(())
+            }
+
+            // every direction is the same swap now, these take the Swap account list.
+            // Their callers do not read return data, so a trip fails the transaction
+            // and the pause is not recorded
+            Instruction::ExchangeSolToToken(args)
+            | Instruction::ExchangeTokenToSol(args)
+            | Instruction::ExchangeTokenToToken(args) => {
+                let mode = SwapMode::ExactIn { amount_in: args.amount, min_amount_out: args.min_amount_out };
+                match processor.swap(program_id, accounts, mode)? {
+                    SwapResult::Executed { .. } => Ok(()),
+                    SwapResult::CircuitBreakerTripped => Err(TokenError::CircuitBreakerTripped.into()),
+                }
+            }
+
+            Instruction::InitializeExchange | Instruction::Migrate(_) => {
+                Err(ProgramError::InvalidInstructionData)
+            }
+
+            Instruction::RegisterToken => {
+                processor.register_token(program_id, accounts)?;
+                Ok(())
+            }
+
+            Instruction::SetTokenEnabled(args) => {
+                processor.set_token_enabled(program_id, accounts, args.enabled)?;
+                Ok(())
+            }
+
+            Instruction::SetRole(args) => {
+                processor.set_role(accounts, args.role, args.authority)?;
+                Ok(())
+            }
+
+            Instruction::RegisterPair(args) => {
+                processor.register_pair(program_id, accounts, args.max_price_change_bps)?;
+                Ok(())
+            }
+
+            Instruction::SetCircuitBreaker(args) => {
+                processor.set_circuit_breaker(program_id, accounts, args.max_price_change_bps)?;
+                Ok(())
+            }
+
+            Instruction::Pause(args) => {
+                processor.set_paused(program_id, accounts, args.scope, true)?;
+                Ok(())
+            }
+
+            Instruction::Unpause(args) => {
+                processor.set_paused(program_id, accounts, args.scope, false)?;
+                Ok(())
+            }
+
+            Instruction::SetMaxPriceAge(args) => {
+                processor.set_max_price_age(program_id, accounts, args.max_price_age)?;
+                Ok(())
+            }
+
+            Instruction::SetMaxManualPriceLifetime(args) => {
+                processor.set_max_manual_price_lifetime(program_id, accounts, args.max_manual_price_lifetime)?;
+                Ok(())
+            }
+
+            Instruction::SetOracle(args) => {
+                processor.set_oracle(program_id, accounts, args.provider)?;
+                Ok(())
+            }
+
+            Instruction::SetMaxConfidence(args) => {
+                processor.set_max_confidence(program_id, accounts, args.max_confidence_bps)?;
+                Ok(())
+            }
+
+            Instruction::SetManualPrice(args) => {
+                processor.set_manual_price(program_id, accounts, args.price, args.exponent, args.expires_at)?;
+                Ok(())
+            }
+
+            Instruction::SetPriceFeeds(args) => {
+                processor.set_price_feeds(program_id, accounts, &args.providers, args.min_valid_feeds)?;
+                Ok(())
+            }
+
+            Instruction::UpdatePrice => {
+                processor.update_price(program_id, accounts)?;
+                Ok(())
+            }
+
+            Instruction::SetPairPricing(args) => {
+                processor.set_pair_pricing(
+                    program_id,
+                    accounts,
+                    args.price_mode,
+                    args.twap_window,
+                    args.max_twap_deviation_bps
+                )?;
+                Ok(())
+            }
+
+            Instruction::Swap(args) => {
+                processor.swap(program_id, accounts, SwapMode::ExactIn {
+                    amount_in: args.amount_in,
+                    min_amount_out: args.min_amount_out,
+                })?;
+                Ok(())
+            }
+
+            Instruction::SwapExactOut(args) => {
+                processor.swap(program_id, accounts, SwapMode::ExactOut {
+                    amount_out: args.amount_out,
+                    max_amount_in: args.max_amount_in,
+                })?;
+                Ok(())
+            }
+
+            Instruction::Quote(args) => {
+                processor.quote(program_id, accounts, args.mode)?;
+                Ok(())
+            }
+
+            Instruction::SetFee(args) => {
+                processor.set_fee(accounts, args.fee_bps)?;
+                Ok(())
+            }
+
+            Instruction::SetPairFee(args) => {
+                processor.set_pair_fee(program_id, accounts, args.fee_bps)?;
+                Ok(())
+            }
+
+            Instruction::InitializeFeeVault => {
+                processor.initialize_fee_vault(program_id, accounts)?;
                 Ok(())
             }
 
-            Instruction::Withdraw { amount, pda_seed, bump_seed } => {
-                Self::withdraw(self, program_id, accounts, amount, &pda_seed, bump_seed)?;
+            Instruction::WithdrawFees(args) => {
+                processor.withdraw_fees(program_id, accounts, args.amount)?;
                 Ok(())
             }
 
-            Instruction::ExchangeSolToToken { amount } => {
-                Self::exchange_sol_to_token(program_id, accounts, amount)?;
+            Instruction::SetPairSpread(args) => {
+                processor.set_pair_spread(program_id, accounts, args.bid_spread_bps, args.ask_spread_bps)?;
                 Ok(())
             }
 
-            Instruction::ExchangeTokenToSol { amount } => {
-                Self::exchange_token_to_sol(program_id, accounts, amount)?;
+            Instruction::SetReferralShare(args) => {
+                processor.set_referral_share(accounts, args.referral_share_bps)?;
                 Ok(())
             }
 
-            Instruction::ExchangeTokenToToken { amount } => {
-                Self::exchange_token_to_token(program_id, accounts, amount)?;
+            Instruction::SetReferrer(args) => {
+                processor.set_referrer(accounts, args.referrer, args.allowed)?;
                 Ok(())
             }
+
+            Instruction::InitializeUserStats => {
+                processor.initialize_user_stats(program_id, accounts)?;
+                Ok(())
+            }
+
+            Instruction::SetFeeTiers(args) => {
+                processor.set_fee_tiers(accounts, args.tiers)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn initialize_exchange(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let accounts = InitializeExchangeAccounts::from_account_infos(accounts)?;
+        let bump_seed = accounts.validate(program_id)?;
+        let InitializeExchangeAccounts { exchange_wallet, exchange_state_account, system_program } = accounts;
+
+        if !exchange_state_account.data_is_empty() {
+            msg!("Exchange is already initialized");
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                exchange_wallet.key,
+                exchange_state_account.key,
+                rent.minimum_balance(ExchangeState::LEN),
+                ExchangeState::LEN as u64,
+                program_id
+            ),
+            &[
+                exchange_wallet.clone(),
+                exchange_state_account.clone(),
+                system_program.clone()
+            ],
+            &[&[EXCHANGE_STATE_SEED, &[bump_seed]]],
+        )?;
+
+        let exchange_state = ExchangeState {
+            is_initialized: true,
+            bump_seed,
+            exchange_wallet: *exchange_wallet.key,
+            admin: *exchange_wallet.key,
+            operator: *exchange_wallet.key,
+            pauser: *exchange_wallet.key,
+            paused: false,
+            mints: Vec::new(),
+            fee_bps: 0,
+            referral_share_bps: 0,
+            referrers: Vec::new(),
+            fee_tiers: Vec::new(),
+        };
+        exchange_state.save(exchange_state_account)?;
+        msg!("exchange initialized");
+
+        Ok(())
+    }
+
+    fn migrate(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        account_type: AccountType
+    ) -> ProgramResult {
+        let accounts = MigrateAccounts::from_account_infos(accounts)?;
+        accounts.validate(program_id)?;
+        let MigrateAccounts { payer, account, system_program } = accounts;
+
+        match account_type {
+            AccountType::ExchangeState => {
+                Self::migrate_account::<ExchangeState>(program_id, payer, account, system_program)
+            }
+            AccountType::TokenConfig => {
+                Self::migrate_account::<TokenConfig>(program_id, payer, account, system_program)
+            }
+            AccountType::PairConfig => {
+                Self::migrate_account::<PairConfig>(program_id, payer, account, system_program)
+            }
+            AccountType::ManualPrice => {
+                Self::migrate_account::<ManualPrice>(program_id, payer, account, system_program)
+            }
+            AccountType::PriceHistory => {
+                Self::migrate_account::<PriceHistory>(program_id, payer, account, system_program)
+            }
+            AccountType::UserStats => {
+                Self::migrate_account::<UserStats>(program_id, payer, account, system_program)
+            }
+        }
+    }
+
+    // rewrites the account in the current layout, growing it if the new one is bigger
+    fn migrate_account<'b, T: StateAccount>(
+        program_id: &Pubkey,
+        payer: &AccountInfo<'b>,
+        account: &AccountInfo<'b>,
+        system_program: &AccountInfo<'b>,
+    ) -> ProgramResult {
+        let state = T::upgrade(&account.data.borrow())?;
+        if state.address(program_id)? != *account.key {
+            msg!("Account {} address mismatch", account.key);
+            return Err(TokenError::MismatchedAccountsError.into());
+        }
+
+        if account.data_len() < T::LEN {
+            let rent = Rent::get()?;
+            let lamports = rent.minimum_balance(T::LEN).saturating_sub(account.lamports());
+            if lamports > 0 {
+                invoke(
+                    &system_instruction::transfer(payer.key, account.key, lamports),
+                    &[
+                        payer.clone(),
+                        account.clone(),
+                        system_program.clone()
+                    ]
+                )?;
+            }
+            account.realloc(T::LEN, true)?;
         }
+        state.save(account)?;
+        msg!("account {} migrated to v{}", account.key, T::VERSION);
+
+        Ok(())
     }
 
     fn deposit(
@@ -77,27 +373,40 @@ impl Processor {
 
     ) -> ProgramResult
     {
-        let accounts_iter = &mut accounts.iter();
-
-        let token_program = next_account_info(accounts_iter)?;
-        let mint = next_account_info(accounts_iter)?;
-        let exchange_wallet = next_account_info(accounts_iter)?;
-        let exchange_token_account = next_account_info(accounts_iter)?;
-        let pda_token_account = next_account_info(accounts_iter)?;
-        let exchange_program_account = next_account_info(accounts_iter)?;
+        let accounts = DepositAccounts::from_account_infos(accounts)?;
+        accounts.validate(program_id, &self.exchange_state, pda_seed, bump_seed)?;
+        let DepositAccounts {
+            token_program,
+            mint,
+            exchange_wallet,
+            exchange_token_account,
+            pda_token_account,
+            exchange_program_account,
+            system_program,
+            token_config_account,
+        } = accounts;
 
-        if !&exchange_wallet.is_signer {
-            msg!("Missing required signature");
-            return Err(ProgramError::MissingRequiredSignature);
+        self.check_role(exchange_wallet, Role::Operator)?;
+        // only registered tokens are held by the exchange
+        let token_config = TokenConfig::load(program_id, token_config_account, mint.key)?;
+        if !token_config.enabled {
+            msg!("Token {} is disabled", mint.key);
+            return Err(TokenError::TokenDisabled.into());
         }
 
-        let mint_key = mint.key.to_string();
-        let key = mint_key;
-        if !self.token_mints.contains(key.as_str()) {
+        if self.exchange_state.find_mint(mint.key).is_none() {
+            if self.exchange_state.mints.len() >= MAX_MINTS {
+                msg!("No room left for a new mint in exchange state");
+                return Err(ProgramError::AccountDataTooSmall);
+            }
 
-            self.token_mints.insert(key);
+            let vault_seeds: &[&[u8]] = &[
+                self.exchange_state.exchange_wallet.as_ref(),
+                pda_seed,
+                &[bump_seed]
+            ];
 
-            let account_len: usize = 1000;
+            let account_len = spl_token::state::Account::LEN;
             let rent = Rent::get()?;
             let rent_lamports = rent.minimum_balance(account_len);
 
@@ -107,18 +416,37 @@ impl Processor {
                     pda_token_account.key,
                     rent_lamports,
                     account_len.to_u64().unwrap(),
-                    exchange_program_account.key
+                    token_program.key
                 ),
                 &[
                     exchange_wallet.clone(),
-                    pda_token_account.clone()
-                ],
-                &[&[
-                    exchange_wallet.key.as_ref(),
-                    pda_seed,
-                    &[bump_seed]]
+                    pda_token_account.clone(),
+                    system_program.clone()
                 ],
+                &[vault_seeds],
+            )?;
+            // vault is its own authority, so only this program can sign for it
+            invoke(
+                &token_instruction::initialize_account3(
+                    token_program.key,
+                    pda_token_account.key,
+                    mint.key,
+                    pda_token_account.key
+                )?,
+                &[
+                    pda_token_account.clone(),
+                    mint.clone(),
+                    token_program.clone()
+                ]
             )?;
+
+            self.exchange_state.mints.push(RegisteredMint {
+                mint: *mint.key,
+                vault: *pda_token_account.key,
+                vault_seed: *pda_seed,
+                vault_bump: bump_seed,
+            });
+            self.exchange_state.save(self.exchange_state_account)?;
             msg!("PDA created for new token");
         }
 
@@ -148,40 +476,31 @@ impl Processor {
 
     fn withdraw(
         &mut self,
-        program_id: &Pubkey,
         accounts: &[AccountInfo],
         amount: u64,
-        pda_seed: &[u8;16],
-        bump_seed: u8
     ) -> ProgramResult {
 
-        let accounts_iter = &mut accounts.iter();
+        let accounts = WithdrawAccounts::from_account_infos(accounts)?;
+        accounts.validate(&self.exchange_state)?;
+        let WithdrawAccounts {
+            token_program,
+            mint,
+            exchange_wallet,
+            exchange_token_account,
+            pda_token_account,
+        } = accounts;
 
-        let token_program = next_account_info(accounts_iter)?;
-        let mint = next_account_info(accounts_iter)?;
-        let exchange_wallet = next_account_info(accounts_iter)?;
-        let exchange_token_account = next_account_info(accounts_iter)?;
-        let pda_token_account = next_account_info(accounts_iter)?;
-        let exchange_program_account = next_account_info(accounts_iter)?;
-
-        if !&exchange_wallet.is_signer {
-            msg!("Missing required signature");
-            return Err(ProgramError::MissingRequiredSignature);
-        }
+        self.check_role(exchange_wallet, Role::Operator)?;
 
-        let mint_key = mint.key.to_string();
-        let key = mint_key.as_str();
-        if !self.token_mints.contains(key) {
-            msg!("No such mint account");
-            return Err(TokenError::MintMismatch.into());
-        }
+        // validate made sure the mint is registered
+        let registered = self.exchange_state.find_mint(mint.key).ok_or(TokenError::MintMismatch)?;
 
         msg!["withdraw tokens to owner (exchange) account"];
         let withdraw_ix = &token_instruction::transfer(
             token_program.key,
             pda_token_account.key,
             exchange_token_account.key,
-            exchange_program_account.key,
+            pda_token_account.key,
             &[],
             amount
         )?;
@@ -191,324 +510,1744 @@ impl Processor {
                 mint.clone(),
                 pda_token_account.clone(),
                 exchange_token_account.clone(),
-                exchange_program_account.clone(),
                 exchange_wallet.clone(),
                 token_program.clone()
             ],
             &[&[
-                exchange_wallet.key.as_ref(),
-                pda_seed,
-                &[bump_seed]]
+                self.exchange_state.exchange_wallet.as_ref(),
+                &registered.vault_seed,
+                &[registered.vault_bump]]
             ],
         )?;
 
         Ok(())
     }
 
-    #[allow(non_snake_case)]
-    fn exchange_sol_to_token(
+    fn register_token(
+        &self,
         program_id: &Pubkey,
         accounts: &[AccountInfo],
-        amount: u64
     ) -> ProgramResult {
+        let accounts = RegisterTokenAccounts::from_account_infos(accounts)?;
+        let (bump_seed, fee_vault_bump) = accounts.validate(program_id)?;
+        let RegisterTokenAccounts {
+            admin,
+            mint,
+            token_config_account,
+            price_feed,
+            system_program,
+            token_program,
+            fee_vault,
+        } = accounts;
+
+        self.check_role(admin, Role::Admin)?;
 
-    let accounts_iter = &mut accounts.iter();
-    let token_program = next_account_info(accounts_iter)?;
-    let exchange_program_account = next_account_info(accounts_iter)?;
-    let mint_A = next_account_info(accounts_iter)?;
-    let mint_B = next_account_info(accounts_iter)?;
-    let client_wallet = next_account_info(accounts_iter)?;
-    let client_token_A_account = next_account_info(accounts_iter)?;
-    let client_associated_token_B_account = next_account_info(accounts_iter)?;
-    let exchange_wallet = next_account_info(accounts_iter)?;
-    let exchange_token_A_account = next_account_info(accounts_iter)?;
-    let exchange_associated_token_B_account = next_account_info(accounts_iter)?;
-    // аккаунты, хранящие данные по стоимости token A, token B
-    let token_A_data_feed_account = next_account_info(accounts_iter)?;
-    let token_B_data_feed_account = next_account_info(accounts_iter)?;
-    // программа, взаимодействующая с oracles
-    let chainlink_program = next_account_info(accounts_iter)?;
-    let token_A_data = Self::get_token_data(
-        &chainlink_program.clone(),
-        &token_A_data_feed_account.clone()
-    )?;
-    let token_B_data = Self::get_token_data(
-        &chainlink_program.clone(),
-        &token_B_data_feed_account.clone()
-    )?;
-
-    //let lamport_price = token_A_data.price / f64::from_u64(LAMPORTS_PER_SOL).unwrap();
-    let token_A_amount = f64::from_u64(amount).unwrap() / LAMPORTS_PER_SOL as f64;
-    let token_B_amount = (token_A_amount * token_A_data.price) / token_B_data.price;
-    msg!["exchanging tokens : token A: {}, amount = {}, token B : {}, amount = {}",
-        &token_A_data.description,
-        &token_A_amount ,
-        &token_B_data.description,
-        &token_B_amount];
-
-    let from_client_ix = &token_instruction::transfer(
-        token_program.key,
-        client_token_A_account.key,
-        exchange_token_A_account.key,
-        client_wallet.key,
-        &[client_wallet.key, exchange_wallet.key],
-        amount
-    )?;
-    invoke(
-        from_client_ix,
-        &[
-            mint_A.clone(),
-            client_token_A_account.clone(),
-            exchange_token_A_account.clone(),
-            client_wallet.clone(),
-            exchange_wallet.clone(),
-            token_program.clone(),
-        ]
-    )?;
-
-    let to_client_ix = &token_instruction::transfer(
-        token_program.key,
-        exchange_associated_token_B_account.key,
-        client_associated_token_B_account.key,
-        exchange_wallet.key,
-        &[exchange_wallet.key, client_wallet.key],
-        (token_B_amount * (10.pow(token_B_data.decimals as u32) as f64)) as u64
-    )?;
-    invoke(to_client_ix,
-                  &[
-                      mint_B.clone(),
-                      exchange_associated_token_B_account.clone(),
-                      client_associated_token_B_account.clone(),
-                      exchange_wallet.clone(),
-                      client_wallet.clone(),
-                      token_program.clone()
-                  ]
+        let mint_data = spl_token::state::Mint::unpack(&mint.data.borrow())?;
+        if !token_config_account.data_is_empty() {
+            msg!("Token is already registered");
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                admin.key,
+                token_config_account.key,
+                rent.minimum_balance(TokenConfig::LEN),
+                TokenConfig::LEN as u64,
+                program_id
+            ),
+            &[
+                admin.clone(),
+                token_config_account.clone(),
+                system_program.clone()
+            ],
+            &[&[TOKEN_CONFIG_SEED, mint.key.as_ref(), &[bump_seed]]],
         )?;
+        Self::create_fee_vault(admin, token_program, mint, fee_vault, system_program, fee_vault_bump)?;
+
+        let token_config = TokenConfig {
+            is_initialized: true,
+            bump_seed,
+            mint: *mint.key,
+            decimals: mint_data.decimals,
+            price_feed: *price_feed.key,
+            enabled: true,
+            paused: false,
+            max_price_age: 0,
+            oracle_provider: OracleProvider::Chainlink,
+            max_confidence_bps: 0,
+            max_manual_price_lifetime: 0,
+            min_valid_feeds: 0,
+            extra_feeds: Vec::new(),
+            fee_vault: *fee_vault.key,
+            fee_vault_bump,
+            fees_collected: 0,
+            fees_withdrawn: 0,
+        };
+        token_config.save(token_config_account)?;
+        msg!("token {} registered with price feed {} and fee vault {}", mint.key, price_feed.key, fee_vault.key);
 
-    Ok(())
+        Ok(())
     }
 
-    #[allow(non_snake_case)]
-    fn exchange_token_to_sol(
+    fn set_token_enabled(
+        &self,
         program_id: &Pubkey,
         accounts: &[AccountInfo],
-        amount: u64
+        enabled: bool
     ) -> ProgramResult {
+        let accounts = UpdateTokenConfigAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+        let UpdateTokenConfigAccounts { admin, mint, token_config_account } = accounts;
 
-        let accounts_iter = &mut accounts.iter();
-        let token_program = next_account_info(accounts_iter)?;
-        let exchange_program_account = next_account_info(accounts_iter)?;
-        let mint_A = next_account_info(accounts_iter)?;
-        let mint_B = next_account_info(accounts_iter)?;
-        let client_wallet = next_account_info(accounts_iter)?;
-        let client_associated_token_A_account = next_account_info(accounts_iter)?;
-        let client_token_B_account = next_account_info(accounts_iter)?;
-        let exchange_wallet = next_account_info(accounts_iter)?;
-        let exchange_associated_token_A_account = next_account_info(accounts_iter)?;
-        let exchange_token_B_account = next_account_info(accounts_iter)?;
-        // аккаунты, хранящие данные по стоимости token A, token B
-        let token_A_data_feed_account = next_account_info(accounts_iter)?;
-        let token_B_data_feed_account = next_account_info(accounts_iter)?;
-        // программа, взаимодействующая с oracles
-        let chainlink_program = next_account_info(accounts_iter)?;
-        let token_A_data = Self::get_token_data(
-            &chainlink_program.clone(),
-            &token_A_data_feed_account.clone()
-        )?;
-        let token_B_data = Self::get_token_data(
-            &chainlink_program.clone(),
-            &token_B_data_feed_account.clone()
-        )?;
+        self.check_role(admin, Role::Admin)?;
 
-        let lamport_price = token_B_data.price / f64::from_u64(LAMPORTS_PER_SOL).unwrap();
-        let token_A_amount = amount / (10.pow(token_A_data.decimals as u32) as u64);
-        let token_B_amount = ((f64::from_u64(amount).unwrap()) * token_A_data.price / token_B_data.price * lamport_price) as u64;
-        msg!["exchanging tokens : token A: {}, amount={}, token B : {}, amount={}",
-            &token_A_data.description,
-            &token_A_amount,
-            &token_B_data.description,
-            &token_B_amount / LAMPORTS_PER_SOL];
+        let mut token_config = TokenConfig::load(program_id, token_config_account, mint.key)?;
+        token_config.enabled = enabled;
+        token_config.save(token_config_account)?;
+        msg!("token {} enabled = {}", mint.key, enabled);
 
-        let from_client_ix = &token_instruction::transfer(
-            token_program.key,
-            client_associated_token_A_account.key,
-            exchange_associated_token_A_account.key,
-            client_wallet.key,
-            &[client_wallet.key, exchange_wallet.key],
-            token_A_amount
-        )?;
-        invoke(
-            from_client_ix,
-            &[
-                mint_A.clone(),
-                client_associated_token_A_account.clone(),
-                exchange_associated_token_A_account.clone(),
-                client_wallet.clone(),
-                exchange_wallet.clone(),
-                token_program.clone(),
-            ]
-        )?;
-        let to_client_ix = &token_instruction::transfer(
-            token_program.key,
-            exchange_token_B_account.key,
-            client_token_B_account.key,
-            exchange_wallet.key,
-            &[exchange_wallet.key, client_wallet.key],
-            token_B_amount
-        )?;
-        invoke(to_client_ix,
-                      &[
-                          mint_B.clone(),
-                          exchange_token_B_account.clone(),
-                          client_token_B_account.clone(),
-                          exchange_wallet.clone(),
-                          client_wallet.clone(),
-                          token_program.clone()
-                      ]
-        )?;
         Ok(())
     }
 
-    #[allow(non_snake_case)]
-    fn exchange_token_to_token(
+    fn set_max_price_age(
+        &self,
         program_id: &Pubkey,
         accounts: &[AccountInfo],
-        amount: u64
+        max_price_age: u32
     ) -> ProgramResult {
+        let accounts = UpdateTokenConfigAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+        let UpdateTokenConfigAccounts { admin, mint, token_config_account } = accounts;
 
-        let accounts_iter = &mut accounts.iter();
-        let token_program = next_account_info(accounts_iter)?;
-        let exchange_program_account = next_account_info(accounts_iter)?;
-        let mint_A = next_account_info(accounts_iter)?;
-        let mint_B = next_account_info(accounts_iter)?;
-        let client_wallet = next_account_info(accounts_iter)?;
-        let client_associated_token_A_account = next_account_info(accounts_iter)?;
-        let client_associated_token_B_account = next_account_info(accounts_iter)?;
-        let exchange_wallet = next_account_info(accounts_iter)?;
-        let exchange_associated_token_A_account = next_account_info(accounts_iter)?;
-        let exchange_associated_token_B_account = next_account_info(accounts_iter)?;
-        // аккаунты, хранящие данные по стоимости token A, token B
-        let token_A_data_feed_account = next_account_info(accounts_iter)?;
-        let token_B_data_feed_account = next_account_info(accounts_iter)?;
-        // программа, взаимодействующая с oracles
-        let chainlink_program = next_account_info(accounts_iter)?;
-        let token_A_data = Self::get_token_data(
-            &chainlink_program.clone(),
-            &token_A_data_feed_account.clone()
-        )?;
-        let token_B_data = Self::get_token_data(
-            &chainlink_program.clone(),
-            &token_B_data_feed_account.clone()
-        )?;
+        self.check_role(admin, Role::Admin)?;
 
-        let token_A_amount = amount;
-        let token_B_amount = ((f64::from_u64(amount).unwrap()) / token_B_data.price) as u64;
-        msg!["exchanging tokens : token A: {}, amount= {}, token B : {}, amount={}",
-            &token_A_data.description,
-            &token_A_amount,
-            &token_B_data.description,
-            &token_B_amount];
+        let mut token_config = TokenConfig::load(program_id, token_config_account, mint.key)?;
+        token_config.max_price_age = max_price_age;
+        token_config.save(token_config_account)?;
+        msg!("token {} max price age = {}s", mint.key, token_config.max_price_age());
 
-        let from_client_ix = &token_instruction::transfer(
-            token_program.key,
-            client_associated_token_A_account.key,
-            exchange_associated_token_A_account.key,
-            client_wallet.key,
-            &[client_wallet.key, exchange_wallet.key],
-            token_A_amount
-        )?;
-        invoke(
-            from_client_ix,
-            &[
-                mint_A.clone(),
-                client_associated_token_A_account.clone(),
-                exchange_associated_token_A_account.clone(),
-                client_wallet.clone(),
-                exchange_wallet.clone(),
-                token_program.clone(),
-            ]
-        )?;
+        Ok(())
+    }
 
-        let (_, bump_seed) = Pubkey::find_program_address(
-            &[
-                &exchange_wallet.key.to_bytes(),
-                &token_program.key.to_bytes(),
-                &mint_A.key.to_bytes(),
-                &mint_B.key.to_bytes(),
-            ],
-            program_id
-        );
+    fn set_max_confidence(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        max_confidence_bps: u16
+    ) -> ProgramResult {
+        let accounts = UpdateTokenConfigAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+        let UpdateTokenConfigAccounts { admin, mint, token_config_account } = accounts;
+
+        self.check_role(admin, Role::Admin)?;
+
+        let mut token_config = TokenConfig::load(program_id, token_config_account, mint.key)?;
+        token_config.max_confidence_bps = max_confidence_bps;
+        token_config.save(token_config_account)?;
+        msg!("token {} max confidence = {} bps", mint.key, token_config.max_confidence_bps());
 
-        let to_client_ix = &token_instruction::transfer(
-            token_program.key,
-            exchange_associated_token_B_account.key,
-            client_associated_token_B_account.key,
-            exchange_program_account.key,
-            &[],
-            token_B_amount
-        )?;
-        invoke_signed(to_client_ix,
-                      &[
-                          exchange_associated_token_B_account.clone(),
-                          client_associated_token_B_account.clone(),
-                          exchange_program_account.clone(),
-                          token_program.clone()
-                      ],
-                      &[&[
-                          &exchange_wallet.key.to_bytes(),
-                          &token_program.key.to_bytes(),
-                          &mint_A.key.to_bytes(),
-                          &mint_B.key.to_bytes(),
-                          &[bump_seed]
-                      ]]
-        )?;
         Ok(())
     }
 
-    fn get_token_data<'a>(
-        chainlink_program: &AccountInfo<'a>,
-        data_feed_program: &AccountInfo<'a>)
-        -> Result<TokenData, ProgramError>
-    {
-        let oracle_data = {
-            let round = chainlink::latest_round_data(
-                chainlink_program.clone(),
-                data_feed_program.clone(),
-            );
-            let decimals = chainlink::decimals(
-                chainlink_program.clone(),
-                data_feed_program.clone(),
-            );
-            let token_description = chainlink::description(
-                chainlink_program.clone(),
-                data_feed_program.clone());
-            match (round, decimals, token_description) {
-                (Ok(round), Ok(decimals), Ok(token_description)) => {
-                    Ok((round, decimals, token_description))
-                }
-                _ => { Err(ProgramError::from(TokenError::OracleDataFeedError)) }
-            }
-        };
-        if let Ok(oracle_data) = oracle_data {
-            let price = f64::from_i128(oracle_data.0.answer).unwrap() /
-                f64::from_i128(10_i128.pow(oracle_data.1 as u32)).unwrap();
-            let trim_usd = oracle_data.2.len() - 6;
-            let (token, _) = oracle_data.2.split_at(trim_usd);
-            msg!("price of token {} is {} USD", &token , price);
-            Ok(
-                TokenData {
-                price,
-                decimals: oracle_data.1,
-                description: String::from(token),
-            })
-        } else {
-            Err(ProgramError::from(TokenError::OracleDataFeedError))
+    fn set_max_manual_price_lifetime(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        max_manual_price_lifetime: u32
+    ) -> ProgramResult {
+        let accounts = UpdateTokenConfigAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+        let UpdateTokenConfigAccounts { admin, mint, token_config_account } = accounts;
+
+        self.check_role(admin, Role::Admin)?;
+
+        let mut token_config = TokenConfig::load(program_id, token_config_account, mint.key)?;
+        token_config.max_manual_price_lifetime = max_manual_price_lifetime;
+        token_config.save(token_config_account)?;
+        msg!("token {} max manual price lifetime = {}s", mint.key, token_config.max_manual_price_lifetime());
+
+        Ok(())
+    }
+
+    fn set_oracle(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        provider: OracleProvider
+    ) -> ProgramResult {
+        let accounts = SetOracleAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+        let SetOracleAccounts { admin, mint, token_config_account, price_feed } = accounts;
+
+        self.check_role(admin, Role::Admin)?;
+
+        let mut token_config = TokenConfig::load(program_id, token_config_account, mint.key)?;
+        if token_config.extra_feeds.iter().any(|feed| feed.price_feed == *price_feed.key) {
+            msg!("Feed {} is already an extra feed of token {}", price_feed.key, mint.key);
+            return Err(ProgramError::InvalidArgument);
         }
+        token_config.oracle_provider = provider;
+        token_config.price_feed = *price_feed.key;
+        token_config.save(token_config_account)?;
+        msg!("token {} priced by {:?} feed {}", mint.key, provider, price_feed.key);
+
+        Ok(())
     }
-}
 
-struct TokenData {
-    price: f64,
-    decimals: u8,
-    description: String,
+    fn set_price_feeds(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        providers: &[OracleProvider],
+        min_valid_feeds: u8
+    ) -> ProgramResult {
+        let accounts = SetPriceFeedsAccounts::from_account_infos(accounts)?;
+        accounts.validate(providers.len())?;
+        let SetPriceFeedsAccounts { admin, mint, token_config_account, price_feeds } = accounts;
+
+        self.check_role(admin, Role::Admin)?;
+
+        if min_valid_feeds as usize > providers.len() {
+            msg!("Quorum of {} cannot be met by {} feeds", min_valid_feeds, providers.len());
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let mut token_config = TokenConfig::load(program_id, token_config_account, mint.key)?;
+        let mut feeds = providers
+            .iter()
+            .zip(price_feeds)
+            .map(|(provider, price_feed)| OracleFeed { provider: *provider, price_feed: *price_feed.key });
+        // validate guarantees at least one feed
+        let primary = feeds.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+        token_config.oracle_provider = primary.provider;
+        token_config.price_feed = primary.price_feed;
+        token_config.extra_feeds = feeds.collect();
+        token_config.min_valid_feeds = min_valid_feeds;
+        token_config.save(token_config_account)?;
+        msg!("token {} priced by median of {} feeds, {} required", mint.key, providers.len(), token_config.min_valid_feeds());
+
+        Ok(())
+    }
+
+    fn set_manual_price(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        price: u64,
+        exponent: i32,
+        expires_at: i64
+    ) -> ProgramResult {
+        let accounts = SetManualPriceAccounts::from_account_infos(accounts)?;
+        let bump_seed = accounts.validate(program_id)?;
+        let SetManualPriceAccounts {
+            operator,
+            mint,
+            token_config_account,
+            manual_price_account,
+            system_program,
+        } = accounts;
+
+        self.check_role(operator, Role::Operator)?;
+
+        // only registered tokens can be priced
+        let token_config = TokenConfig::load(program_id, token_config_account, mint.key)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        if price == 0 {
+            msg!("Manual price must be positive");
+            return Err(ProgramError::InvalidArgument);
+        }
+        oracle::check_manual_price_expiry(expires_at, now, token_config.max_manual_price_lifetime())?;
+
+        if manual_price_account.data_is_empty() {
+            let rent = Rent::get()?;
+            invoke_signed(
+                &system_instruction::create_account(
+                    operator.key,
+                    manual_price_account.key,
+                    rent.minimum_balance(ManualPrice::LEN),
+                    ManualPrice::LEN as u64,
+                    program_id
+                ),
+                &[
+                    operator.clone(),
+                    manual_price_account.clone(),
+                    system_program.clone()
+                ],
+                &[&[MANUAL_PRICE_SEED, mint.key.as_ref(), &[bump_seed]]],
+            )?;
+        } else {
+            ManualPrice::load(program_id, manual_price_account, mint.key)?;
+        }
+
+        let manual_price = ManualPrice {
+            is_initialized: true,
+            bump_seed,
+            mint: *mint.key,
+            price,
+            exponent,
+            updated_at: now,
+            expires_at,
+            updated_by: *operator.key,
+        };
+        manual_price.save(manual_price_account)?;
+        msg!("token {} manual price set to {}e{} until {}", mint.key, price, exponent, expires_at);
+
+        Ok(())
+    }
+
+    fn set_role(
+        &mut self,
+        accounts: &[AccountInfo],
+        role: Role,
+        authority: Pubkey
+    ) -> ProgramResult {
+        let accounts = UpdateExchangeStateAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+
+        self.check_role(accounts.admin, Role::Admin)?;
+
+        self.exchange_state.set_role_authority(role, authority);
+        self.exchange_state.save(self.exchange_state_account)?;
+        msg!("{:?} role set to {}", role, authority);
+
+        Ok(())
+    }
+
+    fn register_pair(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        max_price_change_bps: u16
+    ) -> ProgramResult {
+        let accounts = RegisterPairAccounts::from_account_infos(accounts)?;
+        let bump_seed = accounts.validate(program_id)?;
+        let RegisterPairAccounts {
+            admin,
+            base_mint,
+            quote_mint,
+            base_config_account,
+            quote_config_account,
+            pair_config_account,
+            system_program,
+        } = accounts;
+
+        self.check_role(admin, Role::Admin)?;
+
+        // both sides of the pair have to be registered tokens
+        TokenConfig::load(program_id, base_config_account, base_mint.key)?;
+        TokenConfig::load(program_id, quote_config_account, quote_mint.key)?;
+
+        if !pair_config_account.data_is_empty() {
+            msg!("Pair is already registered");
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                admin.key,
+                pair_config_account.key,
+                rent.minimum_balance(PairConfig::LEN),
+                PairConfig::LEN as u64,
+                program_id
+            ),
+            &[
+                admin.clone(),
+                pair_config_account.clone(),
+                system_program.clone()
+            ],
+            &[&[PAIR_CONFIG_SEED, base_mint.key.as_ref(), quote_mint.key.as_ref(), &[bump_seed]]],
+        )?;
+
+        let pair_config = PairConfig {
+            is_initialized: true,
+            bump_seed,
+            base_mint: *base_mint.key,
+            quote_mint: *quote_mint.key,
+            paused: false,
+            max_price_change_bps,
+            last_price: 0,
+            price_mode: PriceMode::Spot,
+            twap_window: 0,
+            max_twap_deviation_bps: 0,
+            fee_bps: None,
+            bid_spread_bps: 0,
+            ask_spread_bps: 0,
+        };
+        pair_config.save(pair_config_account)?;
+        msg!("pair {}/{} registered", base_mint.key, quote_mint.key);
+
+        Ok(())
+    }
+
+    fn set_circuit_breaker(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        max_price_change_bps: u16
+    ) -> ProgramResult {
+        let accounts = UpdatePairConfigAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+        let UpdatePairConfigAccounts { admin, pair_config_account } = accounts;
+
+        self.check_role(admin, Role::Admin)?;
+
+        let mut pair_config = PairConfig::load(program_id, pair_config_account)?;
+        pair_config.max_price_change_bps = max_price_change_bps;
+        pair_config.save(pair_config_account)?;
+        msg!("circuit breaker set to {} bps", max_price_change_bps);
+
+        Ok(())
+    }
+
+    fn set_pair_pricing(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        price_mode: PriceMode,
+        twap_window: u32,
+        max_twap_deviation_bps: u16
+    ) -> ProgramResult {
+        let accounts = UpdatePairConfigAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+        let UpdatePairConfigAccounts { admin, pair_config_account } = accounts;
+
+        self.check_role(admin, Role::Admin)?;
+
+        // even with updates every block the history reaches back this far
+        let max_window = (MAX_PRICE_OBSERVATIONS as i64 - 1) * PRICE_OBSERVATION_INTERVAL;
+        if twap_window as i64 > max_window {
+            msg!("TWAP window {} is longer than the {} seconds of price history", twap_window, max_window);
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let mut pair_config = PairConfig::load(program_id, pair_config_account)?;
+        pair_config.price_mode = price_mode;
+        pair_config.twap_window = twap_window;
+        pair_config.max_twap_deviation_bps = max_twap_deviation_bps;
+        pair_config.save(pair_config_account)?;
+        msg!(
+            "pair priced at {:?}, TWAP window {} s, max deviation {} bps",
+            price_mode,
+            pair_config.twap_window(),
+            max_twap_deviation_bps
+        );
+
+        Ok(())
+    }
+
+    fn set_fee(
+        &mut self,
+        accounts: &[AccountInfo],
+        fee_bps: u16
+    ) -> ProgramResult {
+        let accounts = UpdateExchangeStateAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+
+        self.check_role(accounts.admin, Role::Admin)?;
+        Self::check_fee(fee_bps)?;
+
+        self.exchange_state.fee_bps = fee_bps;
+        self.exchange_state.save(self.exchange_state_account)?;
+        msg!("exchange fee set to {} bps", fee_bps);
+
+        Ok(())
+    }
+
+    fn set_pair_fee(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        fee_bps: Option<u16>
+    ) -> ProgramResult {
+        let accounts = UpdatePairConfigAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+        let UpdatePairConfigAccounts { admin, pair_config_account } = accounts;
+
+        self.check_role(admin, Role::Admin)?;
+        if let Some(fee_bps) = fee_bps {
+            Self::check_fee(fee_bps)?;
+        }
+
+        let mut pair_config = PairConfig::load(program_id, pair_config_account)?;
+        pair_config.fee_bps = fee_bps;
+        pair_config.save(pair_config_account)?;
+        msg!("pair fee set to {:?} bps, {} bps in effect", fee_bps, pair_config.fee_bps(&self.exchange_state));
+
+        Ok(())
+    }
+
+    fn set_pair_spread(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        bid_spread_bps: u16,
+        ask_spread_bps: u16
+    ) -> ProgramResult {
+        let accounts = UpdatePairConfigAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+        let UpdatePairConfigAccounts { admin, pair_config_account } = accounts;
+
+        self.check_role(admin, Role::Admin)?;
+        if bid_spread_bps > MAX_SPREAD_BPS || ask_spread_bps > MAX_SPREAD_BPS {
+            msg!("Spread is above the maximum of {} bps", MAX_SPREAD_BPS);
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let mut pair_config = PairConfig::load(program_id, pair_config_account)?;
+        pair_config.bid_spread_bps = bid_spread_bps;
+        pair_config.ask_spread_bps = ask_spread_bps;
+        pair_config.save(pair_config_account)?;
+        msg!("pair spread set to {} bps bid, {} bps ask", bid_spread_bps, ask_spread_bps);
+
+        Ok(())
+    }
+
+    fn set_referral_share(
+        &mut self,
+        accounts: &[AccountInfo],
+        referral_share_bps: u16
+    ) -> ProgramResult {
+        let accounts = UpdateExchangeStateAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+
+        self.check_role(accounts.admin, Role::Admin)?;
+        if referral_share_bps > 10_000 {
+            msg!("Referral share of {} bps is more than the whole fee", referral_share_bps);
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        self.exchange_state.referral_share_bps = referral_share_bps;
+        self.exchange_state.save(self.exchange_state_account)?;
+        msg!("referral share set to {} bps of the fee", referral_share_bps);
+
+        Ok(())
+    }
+
+    fn set_referrer(
+        &mut self,
+        accounts: &[AccountInfo],
+        referrer: Pubkey,
+        allowed: bool
+    ) -> ProgramResult {
+        let accounts = UpdateExchangeStateAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+
+        self.check_role(accounts.admin, Role::Admin)?;
+
+        let referrers = &mut self.exchange_state.referrers;
+        if allowed && !referrers.contains(&referrer) {
+            if referrers.len() >= MAX_REFERRERS {
+                msg!("No room left for a new referrer in exchange state");
+                return Err(ProgramError::AccountDataTooSmall);
+            }
+            referrers.push(referrer);
+        } else if !allowed {
+            referrers.retain(|allowlisted| *allowlisted != referrer);
+        }
+        self.exchange_state.save(self.exchange_state_account)?;
+        msg!("referrer {} allowed = {}", referrer, allowed);
+
+        Ok(())
+    }
+
+    fn set_fee_tiers(
+        &mut self,
+        accounts: &[AccountInfo],
+        tiers: Vec<FeeTier>
+    ) -> ProgramResult {
+        let accounts = UpdateExchangeStateAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+
+        self.check_role(accounts.admin, Role::Admin)?;
+        if tiers.len() > MAX_FEE_TIERS {
+            msg!("At most {} fee tiers are supported", MAX_FEE_TIERS);
+            return Err(ProgramError::InvalidArgument);
+        }
+        if tiers.windows(2).any(|pair| pair[0].min_volume_usd >= pair[1].min_volume_usd) {
+            msg!("Fee tiers must be sorted by ascending volume");
+            return Err(ProgramError::InvalidArgument);
+        }
+        for tier in &tiers {
+            Self::check_fee(tier.fee_bps)?;
+        }
+
+        self.exchange_state.fee_tiers = tiers;
+        self.exchange_state.save(self.exchange_state_account)?;
+        msg!("{} fee tiers set", self.exchange_state.fee_tiers.len());
+
+        Ok(())
+    }
+
+    // permissionless, swaps count towards the fee tiers of a wallet once it has stats
+    fn initialize_user_stats(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let accounts = InitializeUserStatsAccounts::from_account_infos(accounts)?;
+        let bump_seed = accounts.validate(program_id)?;
+        let InitializeUserStatsAccounts { payer, wallet, user_stats_account, system_program } = accounts;
+
+        if !user_stats_account.data_is_empty() {
+            msg!("Stats of {} are already initialized", wallet.key);
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                user_stats_account.key,
+                rent.minimum_balance(UserStats::LEN),
+                UserStats::LEN as u64,
+                program_id
+            ),
+            &[
+                payer.clone(),
+                user_stats_account.clone(),
+                system_program.clone()
+            ],
+            &[&[USER_STATS_SEED, wallet.key.as_ref(), &[bump_seed]]],
+        )?;
+
+        let user_stats = UserStats {
+            is_initialized: true,
+            bump_seed,
+            owner: *wallet.key,
+            ..UserStats::default()
+        };
+        user_stats.save(user_stats_account)?;
+        msg!("stats of {} initialized", wallet.key);
+
+        Ok(())
+    }
+
+    // fee of the pair, lowered by the tier the client's rolling volume reaches
+    fn client_fee_bps(&self, pair_config: &PairConfig, client_stats: Option<&UserStats>, now: i64) -> u16 {
+        let fee_bps = pair_config.fee_bps(&self.exchange_state);
+        match client_stats {
+            Some(client_stats) => {
+                let volume_usd = client_stats.rolling_volume(now);
+                let tier_fee_bps = volume::tier_fee_bps(&self.exchange_state.fee_tiers, volume_usd, fee_bps);
+                msg!("client volume {} USD, fee {} bps", volume_usd, tier_fee_bps);
+                tier_fee_bps
+            }
+            None => fee_bps,
+        }
+    }
+
+    fn check_fee(fee_bps: u16) -> ProgramResult {
+        if fee_bps > MAX_FEE_BPS {
+            msg!("Fee of {} bps is above the maximum of {} bps", fee_bps, MAX_FEE_BPS);
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(())
+    }
+
+    // permissionless, creates the token account swap fees in the mint are collected in.
+    // RegisterToken creates it along with the token config, this is for tokens registered before fees
+    fn initialize_fee_vault(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let accounts = InitializeFeeVaultAccounts::from_account_infos(accounts)?;
+        let bump_seed = accounts.validate(program_id)?;
+        let InitializeFeeVaultAccounts {
+            payer,
+            token_program,
+            mint,
+            token_config_account,
+            fee_vault,
+            system_program,
+        } = accounts;
+
+        let mut token_config = TokenConfig::load(program_id, token_config_account, mint.key)?;
+        if token_config.fee_vault != Pubkey::default() || !fee_vault.data_is_empty() {
+            msg!("Fee vault of token {} is already initialized", mint.key);
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        Self::create_fee_vault(payer, token_program, mint, fee_vault, system_program, bump_seed)?;
+
+        token_config.fee_vault = *fee_vault.key;
+        token_config.fee_vault_bump = bump_seed;
+        token_config.save(token_config_account)?;
+        msg!("fee vault {} created for token {}", fee_vault.key, mint.key);
+
+        Ok(())
+    }
+
+    fn create_fee_vault<'b>(
+        payer: &AccountInfo<'b>,
+        token_program: &AccountInfo<'b>,
+        mint: &AccountInfo<'b>,
+        fee_vault: &AccountInfo<'b>,
+        system_program: &AccountInfo<'b>,
+        bump_seed: u8
+    ) -> ProgramResult {
+        let account_len = spl_token::state::Account::LEN;
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                fee_vault.key,
+                rent.minimum_balance(account_len),
+                account_len as u64,
+                token_program.key
+            ),
+            &[
+                payer.clone(),
+                fee_vault.clone(),
+                system_program.clone()
+            ],
+            &[&[FEE_VAULT_SEED, mint.key.as_ref(), &[bump_seed]]],
+        )?;
+        // like the deposit vaults, the fee vault is its own authority
+        invoke(
+            &token_instruction::initialize_account3(
+                token_program.key,
+                fee_vault.key,
+                mint.key,
+                fee_vault.key
+            )?,
+            &[
+                fee_vault.clone(),
+                mint.clone(),
+                token_program.clone()
+            ]
+        )
+    }
+
+    fn withdraw_fees(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        amount: u64
+    ) -> ProgramResult {
+        let accounts = WithdrawFeesAccounts::from_account_infos(accounts)?;
+        accounts.validate()?;
+        let WithdrawFeesAccounts {
+            admin,
+            token_program,
+            mint,
+            token_config_account,
+            fee_vault,
+            destination_token_account,
+        } = accounts;
+
+        self.check_role(admin, Role::Admin)?;
+
+        let mut token_config = TokenConfig::load(program_id, token_config_account, mint.key)?;
+        Self::check_fee_vault(&token_config, fee_vault)?;
+
+        invoke_signed(
+            &token_instruction::transfer_checked(
+                token_program.key,
+                fee_vault.key,
+                mint.key,
+                destination_token_account.key,
+                fee_vault.key,
+                &[],
+                amount,
+                token_config.decimals
+            )?,
+            &[
+                fee_vault.clone(),
+                mint.clone(),
+                destination_token_account.clone(),
+                token_program.clone()
+            ],
+            &[&[FEE_VAULT_SEED, mint.key.as_ref(), &[token_config.fee_vault_bump]]],
+        )?;
+        token_config.fees_withdrawn = token_config.fees_withdrawn
+            .checked_add(amount)
+            .ok_or(TokenError::Overflow)?;
+        token_config.save(token_config_account)?;
+        msg!(
+            "withdrew {} fees of token {}, {} collected and {} withdrawn in total",
+            amount,
+            mint.key,
+            token_config.fees_collected,
+            token_config.fees_withdrawn
+        );
+
+        Ok(())
+    }
+
+    fn check_fee_vault(token_config: &TokenConfig, fee_vault: &AccountInfo) -> ProgramResult {
+        if token_config.fee_vault == Pubkey::default() {
+            msg!("Token {} has no fee vault", token_config.mint);
+            return Err(TokenError::FeeVaultNotInitialized.into());
+        }
+        if token_config.fee_vault != *fee_vault.key {
+            msg!("Fee vault {} does not belong to token {}", fee_vault.key, token_config.mint);
+            return Err(TokenError::MismatchedAccountsError.into());
+        }
+        Ok(())
+    }
+
+    // permissionless crank keeping the price history of a mint going between swaps
+    fn update_price(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let accounts = UpdatePriceAccounts::from_account_infos(accounts)?;
+        let bump_seed = accounts.validate(program_id)?;
+        let UpdatePriceAccounts {
+            payer,
+            mint,
+            token_config_account,
+            price_history_account,
+            system_program,
+            data_feed_account,
+            oracle_program,
+            extra_feed_accounts,
+        } = accounts;
+
+        let token_config = TokenConfig::load(program_id, token_config_account, mint.key)?;
+        let token_data = Self::get_token_data(
+            program_id,
+            &token_config,
+            data_feed_account,
+            extra_feed_accounts,
+            oracle_program
+        )?;
+
+        let mut price_history = if price_history_account.data_is_empty() {
+            let rent = Rent::get()?;
+            invoke_signed(
+                &system_instruction::create_account(
+                    payer.key,
+                    price_history_account.key,
+                    rent.minimum_balance(PriceHistory::LEN),
+                    PriceHistory::LEN as u64,
+                    program_id
+                ),
+                &[
+                    payer.clone(),
+                    price_history_account.clone(),
+                    system_program.clone()
+                ],
+                &[&[PRICE_HISTORY_SEED, mint.key.as_ref(), &[bump_seed]]],
+            )?;
+            PriceHistory {
+                is_initialized: true,
+                bump_seed,
+                mint: *mint.key,
+                ..PriceHistory::default()
+            }
+        } else {
+            PriceHistory::load(program_id, price_history_account, mint.key)?
+        };
+        price_history.record(&token_data.price, Clock::get()?.unix_timestamp)?;
+        price_history.save(price_history_account)?;
+
+        Ok(())
+    }
+
+    // pauser can halt trading, only admin can resume it
+    fn set_paused(
+        &mut self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        scope: PauseScope,
+        paused: bool
+    ) -> ProgramResult {
+        let accounts = PauseAccounts::from_account_infos(accounts, scope)?;
+        accounts.validate()?;
+
+        let role = if paused { Role::Pauser } else { Role::Admin };
+        self.check_role(accounts.authority, role)?;
+
+        match accounts.target {
+            PauseTargetAccounts::Global => {
+                self.exchange_state.paused = paused;
+                self.exchange_state.save(self.exchange_state_account)?;
+            }
+            PauseTargetAccounts::Mint { mint, token_config_account } => {
+                let mut token_config = TokenConfig::load(program_id, token_config_account, mint.key)?;
+                token_config.paused = paused;
+                token_config.save(token_config_account)?;
+            }
+            PauseTargetAccounts::Pair { pair_config_account } => {
+                let mut pair_config = PairConfig::load(program_id, pair_config_account)?;
+                pair_config.paused = paused;
+                if !paused {
+                    // price recorded before the pause is meaningless for the breaker
+                    pair_config.last_price = 0;
+                }
+                pair_config.save(pair_config_account)?;
+            }
+        }
+        msg!("{:?} paused = {}", scope, paused);
+
+        Ok(())
+    }
+
+    fn check_not_paused(&self) -> ProgramResult {
+        if self.exchange_state.paused {
+            msg!("Exchange is paused");
+            return Err(TokenError::ExchangePaused.into());
+        }
+        Ok(())
+    }
+
+    #[allow(non_snake_case)]
+    fn check_pair_config(
+        program_id: &Pubkey,
+        pair_config_account: &AccountInfo,
+        mint_A: &AccountInfo,
+        mint_B: &AccountInfo,
+    ) -> Result<PairConfig, ProgramError> {
+        let pair_config = PairConfig::load(program_id, pair_config_account)?;
+        if !pair_config.contains(mint_A.key, mint_B.key) {
+            msg!("Pair config does not belong to these mints");
+            return Err(TokenError::MintMismatch.into());
+        }
+        if pair_config.paused {
+            msg!("Pair {}/{} is paused", pair_config.base_mint, pair_config.quote_mint);
+            return Err(TokenError::PairPaused.into());
+        }
+        Ok(pair_config)
+    }
+
+    // records the new pair price, or pauses the pair if it moved too far since the last swap.
+    // returns false when the breaker tripped: the pause has to be committed, so the caller
+    // must skip the swap and return Ok instead of failing the transaction, telling the client
+    // through SwapResult::CircuitBreakerTripped return data. Without `commit` nothing is saved
+    #[allow(non_snake_case)]
+    fn update_pair_price(
+        pair_config_account: &AccountInfo,
+        pair_config: &mut PairConfig,
+        mint_A: &AccountInfo,
+        token_A_data: &TokenData,
+        token_B_data: &TokenData,
+        commit: bool
+    ) -> Result<bool, ProgramError> {
+        let price = if pair_config.base_mint == *mint_A.key {
+            pricing::pair_price(&token_A_data.price, &token_B_data.price)?
+        } else {
+            pricing::pair_price(&token_B_data.price, &token_A_data.price)?
+        };
+
+        let last_price = pair_config.last_price;
+        if pair_config.max_price_change_bps > 0 && last_price > 0 {
+            let change = price.abs_diff(last_price);
+            let change_bps = change
+                .checked_mul(10_000)
+                .ok_or(TokenError::Overflow)?
+                / last_price;
+            if change_bps > pair_config.max_price_change_bps as u128 {
+                msg!("Circuit breaker tripped: price moved {} bps, pair is paused", change_bps);
+                pair_config.paused = true;
+                if commit {
+                    pair_config.save(pair_config_account)?;
+                }
+                return Ok(false);
+            }
+        }
+
+        pair_config.last_price = price;
+        if commit {
+            pair_config.save(pair_config_account)?;
+        }
+        Ok(true)
+    }
+
+    fn check_role(&self, signer: &AccountInfo, role: Role) -> ProgramResult {
+        if !signer.is_signer {
+            msg!("Missing required signature");
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if signer.key != self.exchange_state.role_authority(role) {
+            msg!("{} does not hold {:?} role", signer.key, role);
+            return Err(TokenError::Unauthorized.into());
+        }
+        Ok(())
+    }
+
+    // loads registry entry of the mint and makes sure the client passed its pinned feed
+    fn check_token_config(
+        program_id: &Pubkey,
+        token_config_account: &AccountInfo,
+        mint: &AccountInfo,
+        data_feed_account: &AccountInfo,
+    ) -> Result<TokenConfig, ProgramError> {
+        let token_config = TokenConfig::load(program_id, token_config_account, mint.key)?;
+        if !token_config.enabled {
+            msg!("Token {} is disabled", mint.key);
+            return Err(TokenError::TokenDisabled.into());
+        }
+        if token_config.paused {
+            msg!("Token {} is paused", mint.key);
+            return Err(TokenError::TokenPaused.into());
+        }
+        if token_config.price_feed != *data_feed_account.key {
+            msg!("Data feed {} is not registered for token {}", data_feed_account.key, mint.key);
+            return Err(TokenError::OracleFeedMismatch.into());
+        }
+        // amounts are scaled by the registered decimals, they must still be the mint's
+        let mint_data = spl_token::state::Mint::unpack(&mint.data.borrow())?;
+        if mint_data.decimals != token_config.decimals {
+            msg!("Token {} has {} decimals, {} registered", mint.key, mint_data.decimals, token_config.decimals);
+            return Err(TokenError::MintDecimalsMismatch.into());
+        }
+        Ok(token_config)
+    }
+
+    // any two registered mints, wrapped SOL included: client pays A and gets B at oracle price
+    #[allow(non_snake_case)]
+    fn swap(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        mode: SwapMode
+    ) -> Result<SwapResult, ProgramError> {
+        self.check_not_paused()?;
+
+        let accounts = SwapAccounts::from_account_infos(accounts)?;
+        accounts.validate(program_id, &self.exchange_state)?;
+        let PricedSwap {
+            token_A_data,
+            token_B_data,
+            token_A_oracle_price,
+            mut token_B_config,
+            pair_config,
+        } = match Self::price_swap(program_id, accounts.quote_accounts(), true)? {
+            Some(priced) => priced,
+            None => {
+                msg!("Swap skipped, no tokens moved");
+                return Self::set_swap_result(SwapResult::CircuitBreakerTripped);
+            }
+        };
+        let SwapAccounts {
+            token_program,
+            mint_A,
+            mint_B,
+            client_wallet,
+            client_token_A_account,
+            client_token_B_account,
+            exchange_wallet,
+            exchange_token_A_account,
+            exchange_token_B_account,
+            token_B_config_account,
+            token_B_fee_vault,
+            referrer_token_account,
+            client_stats_account,
+            ..
+        } = accounts;
+
+        let now = Clock::get()?.unix_timestamp;
+        let client_stats = match client_stats_account {
+            Some(client_stats_account) => Some(UserStats::load(program_id, client_stats_account, client_wallet.key)?),
+            None => None,
+        };
+
+        // limits are checked before any tokens move
+        let fee_bps = self.client_fee_bps(&pair_config, client_stats.as_ref(), now);
+        let amounts = pricing::swap_amounts(
+            mode,
+            token_A_data.decimals,
+            &token_A_data.price,
+            token_B_data.decimals,
+            &token_B_data.price,
+            fee_bps
+        )?;
+        if amounts.fee > 0 {
+            Self::check_fee_vault(&token_B_config, token_B_fee_vault)?;
+        }
+        let token_A_amount = amounts.amount_in;
+        let token_B_amount = amounts.amount_out;
+        msg!["exchanging tokens : token A: {}, amount = {}, token B : {}, amount = {}, fee = {}",
+            &token_A_data.mint,
+            &token_A_amount,
+            &token_B_data.mint,
+            &token_B_amount,
+            &amounts.fee];
+
+        let from_client_ix = &token_instruction::transfer_checked(
+            token_program.key,
+            client_token_A_account.key,
+            mint_A.key,
+            exchange_token_A_account.key,
+            client_wallet.key,
+            &[client_wallet.key, exchange_wallet.key],
+            token_A_amount,
+            token_A_data.decimals
+        )?;
+        invoke(
+            from_client_ix,
+            &[
+                mint_A.clone(),
+                client_token_A_account.clone(),
+                exchange_token_A_account.clone(),
+                client_wallet.clone(),
+                exchange_wallet.clone(),
+                token_program.clone(),
+            ]
+        )?;
+
+        // exchange token accounts are owned by exchange wallet
+        let to_client_ix = &token_instruction::transfer_checked(
+            token_program.key,
+            exchange_token_B_account.key,
+            mint_B.key,
+            client_token_B_account.key,
+            exchange_wallet.key,
+            &[exchange_wallet.key, client_wallet.key],
+            token_B_amount,
+            token_B_data.decimals
+        )?;
+        invoke(
+            to_client_ix,
+            &[
+                mint_B.clone(),
+                exchange_token_B_account.clone(),
+                client_token_B_account.clone(),
+                exchange_wallet.clone(),
+                client_wallet.clone(),
+                token_program.clone()
+            ]
+        )?;
+
+        // validate made sure the referrer is allowlisted
+        let referral = match referrer_token_account {
+            Some(referrer_token_account) => Some((
+                referrer_token_account,
+                pricing::referral_share(amounts.fee, self.exchange_state.referral_share_bps)?
+            )),
+            None => None,
+        };
+        let mut fee = amounts.fee;
+        if let Some((referrer_token_account, share)) = referral.filter(|(_, share)| *share > 0) {
+            msg!("referrer {} gets {} of the fee", referrer_token_account.key, share);
+            Self::transfer_fee(
+                token_program,
+                mint_B,
+                exchange_token_B_account,
+                referrer_token_account,
+                exchange_wallet,
+                share,
+                token_B_data.decimals
+            )?;
+            fee -= share;
+        }
+        if fee > 0 {
+            Self::transfer_fee(
+                token_program,
+                mint_B,
+                exchange_token_B_account,
+                token_B_fee_vault,
+                exchange_wallet,
+                fee,
+                token_B_data.decimals
+            )?;
+            token_B_config.fees_collected = token_B_config.fees_collected
+                .checked_add(fee)
+                .ok_or(TokenError::Overflow)?;
+            token_B_config.save(token_B_config_account)?;
+        }
+
+        // volume is valued at the oracle price, without the spread
+        if let (Some(client_stats_account), Some(mut client_stats)) = (client_stats_account, client_stats) {
+            let volume_usd = pricing::usd_value(token_A_amount, token_A_data.decimals, &token_A_oracle_price)?;
+            client_stats.record_volume(volume_usd, now);
+            client_stats.save(client_stats_account)?;
+        }
+        Self::set_swap_result(SwapResult::Executed {
+            amount_in: token_A_amount,
+            amount_out: token_B_amount,
+            fee: amounts.fee,
+        })
+    }
+
+    fn set_swap_result(result: SwapResult) -> Result<SwapResult, ProgramError> {
+        set_return_data(&result.try_to_vec()?);
+        Ok(result)
+    }
+
+    // moves part of the fee out of the exchange token account of B
+    fn transfer_fee<'b>(
+        token_program: &AccountInfo<'b>,
+        mint: &AccountInfo<'b>,
+        exchange_token_account: &AccountInfo<'b>,
+        destination: &AccountInfo<'b>,
+        exchange_wallet: &AccountInfo<'b>,
+        amount: u64,
+        decimals: u8
+    ) -> ProgramResult {
+        let fee_ix = &token_instruction::transfer_checked(
+            token_program.key,
+            exchange_token_account.key,
+            mint.key,
+            destination.key,
+            exchange_wallet.key,
+            &[exchange_wallet.key],
+            amount,
+            decimals
+        )?;
+        invoke(
+            fee_ix,
+            &[
+                mint.clone(),
+                exchange_token_account.clone(),
+                destination.clone(),
+                exchange_wallet.clone(),
+                token_program.clone()
+            ]
+        )
+    }
+
+    // read-only run of the swap pricing for clients, result goes to return data
+    #[allow(non_snake_case)]
+    fn quote(
+        &self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        mode: SwapMode
+    ) -> ProgramResult {
+        self.check_not_paused()?;
+
+        let accounts = QuoteAccounts::from_account_infos(accounts, program_id)?;
+        accounts.validate()?;
+        // quotes are not signed by the client, any stats can be used to see their tier
+        let client_stats = match accounts.client_stats_account {
+            Some(client_stats_account) => Some(UserStats::load_account(program_id, client_stats_account)?),
+            None => None,
+        };
+        let PricedSwap { token_A_data, token_B_data, pair_config, .. } = match Self::price_swap(program_id, accounts, false)? {
+            Some(priced) => priced,
+            None => {
+                msg!("Swap would trip the circuit breaker of the pair");
+                return Err(TokenError::PairPaused.into());
+            }
+        };
+        let fee_bps = self.client_fee_bps(&pair_config, client_stats.as_ref(), Clock::get()?.unix_timestamp);
+        let amounts = pricing::swap_amounts(
+            mode,
+            token_A_data.decimals,
+            &token_A_data.price,
+            token_B_data.decimals,
+            &token_B_data.price,
+            fee_bps
+        )?;
+        let quote = QuoteResult {
+            amount_in: amounts.amount_in,
+            amount_out: amounts.amount_out,
+            fee: amounts.fee,
+            price: pricing::pair_price(&token_A_data.price, &token_B_data.price)?,
+        };
+        msg!("quote: {} -> {}, fee {}, price {}", quote.amount_in, quote.amount_out, quote.fee, quote.price);
+        set_return_data(&quote.try_to_vec()?);
+
+        Ok(())
+    }
+
+    // prices A and B the way the pair is configured. With `commit` the spot prices and the pair
+    // price are saved, None then means the circuit breaker tripped and the swap must be skipped
+    #[allow(non_snake_case)]
+    fn price_swap(
+        program_id: &Pubkey,
+        accounts: QuoteAccounts,
+        commit: bool
+    ) -> Result<Option<PricedSwap>, ProgramError> {
+        let QuoteAccounts {
+            mint_A,
+            mint_B,
+            token_A_data_feed_account,
+            token_B_data_feed_account,
+            chainlink_program,
+            token_A_config_account,
+            token_B_config_account,
+            pair_config_account,
+            extra_feed_accounts,
+            ..
+        } = accounts;
+        let token_A_config = Self::check_token_config(program_id, token_A_config_account, mint_A, token_A_data_feed_account)?;
+        let token_B_config = Self::check_token_config(program_id, token_B_config_account, mint_B, token_B_data_feed_account)?;
+        let pair_config = Self::check_pair_config(program_id, pair_config_account, mint_A, mint_B)?;
+        let (token_A_extra_feeds, token_B_extra_feeds) = Self::split_extra_feeds(extra_feed_accounts, &token_A_config)?;
+        let token_A_data = Self::get_token_data(program_id, &token_A_config, token_A_data_feed_account, token_A_extra_feeds, chainlink_program)?;
+        let token_B_data = Self::get_token_data(program_id, &token_B_config, token_B_data_feed_account, token_B_extra_feeds, chainlink_program)?;
+        Self::price_tokens(program_id, &accounts, token_A_data, token_B_data, token_B_config, pair_config, commit)
+    }
+
+    // rest of price_swap once the oracle prices are known: price mode and circuit breaker
+    #[allow(non_snake_case)]
+    fn price_tokens(
+        program_id: &Pubkey,
+        accounts: &QuoteAccounts,
+        token_A_data: TokenData,
+        token_B_data: TokenData,
+        token_B_config: TokenConfig,
+        mut pair_config: PairConfig,
+        commit: bool
+    ) -> Result<Option<PricedSwap>, ProgramError> {
+        let QuoteAccounts {
+            mint_A,
+            pair_config_account,
+            token_A_price_history_account,
+            token_B_price_history_account,
+            ..
+        } = *accounts;
+        let token_A_data = Self::apply_price_mode(program_id, &pair_config, token_A_price_history_account, token_A_data, commit)?;
+        let token_B_data = Self::apply_price_mode(program_id, &pair_config, token_B_price_history_account, token_B_data, commit)?;
+        if !Self::update_pair_price(pair_config_account, &mut pair_config, mint_A, &token_A_data, &token_B_data, commit)? {
+            return Ok(None);
+        }
+        // the breaker watches the mid price, the client trades at bid or ask
+        let token_A_oracle_price = token_A_data.price;
+        let (token_A_data, token_B_data) = Self::apply_spread(&pair_config, mint_A, token_A_data, token_B_data)?;
+        Ok(Some(PricedSwap { token_A_data, token_B_data, token_A_oracle_price, token_B_config, pair_config }))
+    }
+
+    fn get_token_data<'b>(
+        program_id: &Pubkey,
+        token_config: &TokenConfig,
+        data_feed_account: &AccountInfo<'b>,
+        extra_feed_accounts: &[AccountInfo<'b>],
+        oracle_program: &AccountInfo<'b>)
+        -> Result<TokenData, ProgramError>
+    {
+        let price = oracle::load_price(
+            program_id,
+            token_config,
+            data_feed_account,
+            extra_feed_accounts,
+            oracle_program,
+            Clock::get()?.unix_timestamp
+        )?;
+        msg!("price of token {} is {} USD", token_config.mint, price);
+        Ok(TokenData { mint: token_config.mint, decimals: token_config.decimals, price })
+    }
+
+    // records the spot price in the mint's history, then prices the token as the pair is configured.
+    // Spot pairs without a deviation limit do not read the history, so they swap before the crank
+    // created it and only feed it afterwards. Without `commit` the history is not saved
+    fn apply_price_mode(
+        program_id: &Pubkey,
+        pair_config: &PairConfig,
+        price_history_account: &AccountInfo,
+        token_data: TokenData,
+        commit: bool
+    ) -> Result<TokenData, ProgramError> {
+        let uses_twap = pair_config.price_mode == PriceMode::Twap || pair_config.max_twap_deviation_bps > 0;
+        if !uses_twap && price_history_account.data_is_empty() {
+            let (price_history_key, _) =
+                Pubkey::find_program_address(&[PRICE_HISTORY_SEED, token_data.mint.as_ref()], program_id);
+            if price_history_key != *price_history_account.key {
+                msg!("Price history {} address mismatch", price_history_account.key);
+                return Err(TokenError::MismatchedAccountsError.into());
+            }
+            return Ok(token_data);
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        let mut price_history = PriceHistory::load(program_id, price_history_account, &token_data.mint)?;
+        price_history.record(&token_data.price, now)?;
+        if commit {
+            price_history.save(price_history_account)?;
+        }
+
+        if !uses_twap {
+            return Ok(token_data);
+        }
+        let twap = price_history.twap(now, pair_config.twap_window())?;
+        if pair_config.max_twap_deviation_bps > 0 {
+            twap::check_deviation(&token_data.price, &twap, pair_config.max_twap_deviation_bps)?;
+        }
+        match pair_config.price_mode {
+            PriceMode::Spot => Ok(token_data),
+            PriceMode::Twap => {
+                msg!("TWAP of token {} is {} USD", token_data.mint, twap);
+                Ok(TokenData { price: twap, ..token_data })
+            }
+        }
+    }
+
+    // client selling the base token gets the bid, buying it pays the ask
+    #[allow(non_snake_case)]
+    fn apply_spread(
+        pair_config: &PairConfig,
+        mint_A: &AccountInfo,
+        token_A_data: TokenData,
+        token_B_data: TokenData
+    ) -> Result<(TokenData, TokenData), ProgramError> {
+        if pair_config.base_mint == *mint_A.key {
+            if pair_config.bid_spread_bps == 0 {
+                return Ok((token_A_data, token_B_data));
+            }
+            let price = pricing::bid_price(&token_A_data.price, pair_config.bid_spread_bps)?;
+            msg!("token {} sold at bid {} USD", token_A_data.mint, price);
+            Ok((TokenData { price, ..token_A_data }, token_B_data))
+        } else {
+            if pair_config.ask_spread_bps == 0 {
+                return Ok((token_A_data, token_B_data));
+            }
+            let price = pricing::ask_price(&token_B_data.price, pair_config.ask_spread_bps)?;
+            msg!("token {} bought at ask {} USD", token_B_data.mint, price);
+            Ok((token_A_data, TokenData { price, ..token_B_data }))
+        }
+    }
+
+    // extra feeds of token A come first, the rest belong to token B
+    #[allow(non_snake_case)]
+    fn split_extra_feeds<'b, 'c>(
+        extra_feed_accounts: &'c [AccountInfo<'b>],
+        token_A_config: &TokenConfig
+    ) -> Result<(&'c [AccountInfo<'b>], &'c [AccountInfo<'b>]), ProgramError> {
+        if extra_feed_accounts.len() < token_A_config.extra_feeds.len() {
+            msg!("Missing extra feeds of token {}", token_A_config.mint);
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        Ok(extra_feed_accounts.split_at(token_A_config.extra_feeds.len()))
+    }
+}
+
+struct TokenData {
+    mint: Pubkey,
+    // of the mint, the oracle's are in `price`
+    decimals: u8,
+    price: Price,
+}
+
+// everything a swap is priced with
+#[allow(non_snake_case)]
+struct PricedSwap {
+    token_A_data: TokenData,
+    token_B_data: TokenData,
+    // price of A before the spread
+    token_A_oracle_price: Price,
+    token_B_config: TokenConfig,
+    pair_config: PairConfig,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::system_program;
+    use crate::instruction::{SetFeeTiersArgs, SetReferrerArgs, SetRoleArgs};
+
+    struct Exchange {
+        program_id: Pubkey,
+        state_key: Pubkey,
+        data: Vec<u8>,
+        admin: Pubkey,
+    }
+
+    impl Exchange {
+        fn new() -> Self {
+            let program_id = Pubkey::new_unique();
+            let (state_key, bump_seed) = Pubkey::find_program_address(&[EXCHANGE_STATE_SEED], &program_id);
+            let admin = Pubkey::new_unique();
+            let state = ExchangeState {
+                is_initialized: true,
+                bump_seed,
+                exchange_wallet: admin,
+                admin,
+                operator: admin,
+                pauser: admin,
+                ..ExchangeState::default()
+            };
+            let mut data = vec![0; ExchangeState::LEN];
+            state.pack(&mut data).unwrap();
+            Exchange { program_id, state_key, data, admin }
+        }
+
+        fn state(&self) -> ExchangeState {
+            ExchangeState::unpack(&self.data).unwrap()
+        }
+
+        fn set_role(&mut self, signer: &Pubkey, is_signer: bool, role: Role, authority: Pubkey) -> ProgramResult {
+            self.update_state(signer, is_signer, Instruction::SetRole(SetRoleArgs { role, authority }))
+        }
+
+        fn set_fee_tiers(&mut self, tiers: Vec<FeeTier>) -> ProgramResult {
+            let admin = self.admin;
+            self.update_state(&admin, true, Instruction::SetFeeTiers(SetFeeTiersArgs { tiers }))
+        }
+
+        fn set_referrer(&mut self, referrer: Pubkey, allowed: bool) -> ProgramResult {
+            let admin = self.admin;
+            self.update_state(&admin, true, Instruction::SetReferrer(SetReferrerArgs { referrer, allowed }))
+        }
+
+        // instructions taking only the exchange state and a role holder
+        fn update_state(&mut self, signer: &Pubkey, is_signer: bool, instruction: Instruction) -> ProgramResult {
+            let data = instruction.pack();
+            let (mut state_lamports, mut signer_lamports) = (0, 0);
+            let mut signer_data = [];
+            let system_program_id = system_program::id();
+            let accounts = [
+                AccountInfo::new(
+                    &self.state_key, false, true, &mut state_lamports, &mut self.data, &self.program_id, false, 0
+                ),
+                AccountInfo::new(
+                    signer, is_signer, false, &mut signer_lamports, &mut signer_data, &system_program_id, false, 0
+                ),
+            ];
+            Processor::process_instruction(&self.program_id, &accounts, &data)
+        }
+    }
+
+    fn apply_price_mode(
+        pair_config: &PairConfig,
+        price_history_key: &Pubkey,
+        program_id: &Pubkey
+    ) -> Result<Price, ProgramError> {
+        let (mut lamports, mut data) = (0, []);
+        let system_program_id = system_program::id();
+        let price_history_account = AccountInfo::new(
+            price_history_key, false, true, &mut lamports, &mut data, &system_program_id, false, 0
+        );
+        let token_data = TokenData { mint: pair_config.base_mint, decimals: 6, price: Price { value: 150, decimals: 2 } };
+        Processor::apply_price_mode(program_id, pair_config, &price_history_account, token_data, true)
+            .map(|token_data| token_data.price)
+    }
+
+    #[test]
+    fn spot_pair_swaps_before_price_history_exists() {
+        let program_id = Pubkey::new_unique();
+        let pair_config = PairConfig { base_mint: Pubkey::new_unique(), ..PairConfig::default() };
+        let (price_history_key, _) =
+            Pubkey::find_program_address(&[PRICE_HISTORY_SEED, pair_config.base_mint.as_ref()], &program_id);
+        assert_eq!(
+            apply_price_mode(&pair_config, &price_history_key, &program_id),
+            Ok(Price { value: 150, decimals: 2 })
+        );
+        assert_eq!(
+            apply_price_mode(&pair_config, &Pubkey::new_unique(), &program_id),
+            Err(TokenError::MismatchedAccountsError.into())
+        );
+
+        // TWAP pricing or a deviation limit need the history
+        for pair_config in [
+            PairConfig { price_mode: PriceMode::Twap, ..pair_config.clone() },
+            PairConfig { max_twap_deviation_bps: 100, ..pair_config.clone() },
+        ] {
+            assert!(apply_price_mode(&pair_config, &price_history_key, &program_id).is_err());
+        }
+    }
+
+    const SOL: Price = Price { value: 2_012_345_678, decimals: 8 };
+    const USDC: Price = Price { value: 100_000_000, decimals: 8 };
+
+    // prices a swap of SOL (base) and USDC out of `mint_A` the way quote (`commit` false) and swap do,
+    // returns its amounts and the pair config left in the account
+    #[allow(non_snake_case)]
+    fn price_pair(
+        pair_config: &PairConfig,
+        mint_A: &Pubkey,
+        mode: SwapMode,
+        commit: bool
+    ) -> (Result<Option<pricing::SwapAmounts>, ProgramError>, PairConfig) {
+        let program_id = Pubkey::new_unique();
+        let mint_B = if *mint_A == pair_config.base_mint { pair_config.quote_mint } else { pair_config.base_mint };
+        let token_data = |mint: &Pubkey| match *mint == pair_config.base_mint {
+            true => TokenData { mint: *mint, decimals: 9, price: SOL },
+            false => TokenData { mint: *mint, decimals: 6, price: USDC },
+        };
+        let price_history =
+            |mint: &Pubkey| Pubkey::find_program_address(&[PRICE_HISTORY_SEED, mint.as_ref()], &program_id).0;
+        let keys = [*mint_A, mint_B, Pubkey::new_unique(), price_history(mint_A), price_history(&mint_B)];
+        let mut lamports = [0; 5];
+        let mut data = [Vec::new(), Vec::new(), vec![0; PairConfig::LEN], Vec::new(), Vec::new()];
+        pair_config.pack(&mut data[2]).unwrap();
+        let infos: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .map(|((key, lamports), data)| AccountInfo::new(key, false, true, lamports, data, &program_id, false, 0))
+            .collect();
+        let accounts = QuoteAccounts {
+            mint_A: &infos[0],
+            mint_B: &infos[1],
+            token_A_data_feed_account: &infos[0],
+            token_B_data_feed_account: &infos[1],
+            chainlink_program: &infos[0],
+            token_A_config_account: &infos[0],
+            token_B_config_account: &infos[1],
+            pair_config_account: &infos[2],
+            token_A_price_history_account: &infos[3],
+            token_B_price_history_account: &infos[4],
+            client_stats_account: None,
+            extra_feed_accounts: &[],
+        };
+        let priced = Processor::price_tokens(
+            &program_id,
+            &accounts,
+            token_data(mint_A),
+            token_data(&mint_B),
+            TokenConfig::default(),
+            pair_config.clone(),
+            commit
+        );
+        let amounts = priced.and_then(|priced| {
+            priced
+                .map(|PricedSwap { token_A_data, token_B_data, pair_config, .. }| pricing::swap_amounts(
+                    mode,
+                    token_A_data.decimals,
+                    &token_A_data.price,
+                    token_B_data.decimals,
+                    &token_B_data.price,
+                    pair_config.fee_bps(&ExchangeState::default())
+                ))
+                .transpose()
+        });
+        drop(infos);
+        (amounts, PairConfig::unpack(&data[2]).unwrap())
+    }
+
+    fn sol_usdc_pair() -> PairConfig {
+        PairConfig {
+            is_initialized: true,
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            max_price_change_bps: 500,
+            last_price: pricing::pair_price(&SOL, &USDC).unwrap() - 1_000_000,
+            fee_bps: Some(30),
+            ..PairConfig::default()
+        }
+    }
+
+    #[test]
+    fn quote_prices_like_swap_and_saves_nothing() {
+        let pair_config = PairConfig { bid_spread_bps: 25, ask_spread_bps: 40, ..sol_usdc_pair() };
+        let (base, quote) = (pair_config.base_mint, pair_config.quote_mint);
+        for (sold_mint, mode) in [
+            (base, SwapMode::ExactIn { amount_in: 1_000_000_000, min_amount_out: 0 }),
+            (base, SwapMode::ExactOut { amount_out: 20_000_000, max_amount_in: u64::MAX }),
+            (quote, SwapMode::ExactIn { amount_in: 20_000_000, min_amount_out: 0 }),
+            (quote, SwapMode::ExactOut { amount_out: 1_000_000_000, max_amount_in: u64::MAX }),
+        ] {
+            let (quoted, quoted_pair) = price_pair(&pair_config, &sold_mint, mode, false);
+            let (swapped, swapped_pair) = price_pair(&pair_config, &sold_mint, mode, true);
+            assert!(matches!(quoted, Ok(Some(_))), "{:?}", mode);
+            assert_eq!(quoted, swapped, "{:?}", mode);
+            assert_eq!(quoted_pair, pair_config);
+            // the breaker watches the mid price, not the spread one
+            let last_price = pricing::pair_price(&SOL, &USDC).unwrap();
+            assert_eq!(swapped_pair, PairConfig { last_price, ..pair_config.clone() });
+        }
+
+        // 1 SOL sold at the bid, 20.073148 USDC, less 30 bps rounded up
+        let mode = SwapMode::ExactIn { amount_in: 1_000_000_000, min_amount_out: 0 };
+        assert_eq!(
+            price_pair(&pair_config, &base, mode, false).0,
+            Ok(Some(pricing::SwapAmounts { amount_in: 1_000_000_000, amount_out: 20_012_928, fee: 60_220 }))
+        );
+        // and a limit the swap would miss fails the quote the same way
+        let mode = SwapMode::ExactIn { amount_in: 1_000_000_000, min_amount_out: 20_012_929 };
+        for commit in [false, true] {
+            assert_eq!(price_pair(&pair_config, &base, mode, commit).0, Err(TokenError::SlippageExceeded.into()));
+        }
+    }
+
+    #[test]
+    fn quote_does_not_trip_circuit_breaker() {
+        let pair_config = PairConfig { last_price: pricing::pair_price(&SOL, &USDC).unwrap() / 2, ..sol_usdc_pair() };
+        let mode = SwapMode::ExactIn { amount_in: 1_000_000_000, min_amount_out: 0 };
+        assert_eq!(price_pair(&pair_config, &pair_config.base_mint, mode, false), (Ok(None), pair_config.clone()));
+        assert_eq!(
+            price_pair(&pair_config, &pair_config.base_mint, mode, true),
+            (Ok(None), PairConfig { paused: true, ..pair_config.clone() })
+        );
+    }
+
+    #[test]
+    fn set_referrer_allowlists_once() {
+        let mut exchange = Exchange::new();
+        let (referrer, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        exchange.set_referrer(referrer, true).unwrap();
+        exchange.set_referrer(referrer, true).unwrap();
+        exchange.set_referrer(other, true).unwrap();
+        assert_eq!(exchange.state().referrers, [referrer, other]);
+
+        exchange.set_referrer(referrer, false).unwrap();
+        assert!(!exchange.state().is_referrer(&referrer));
+        assert_eq!(exchange.state().referrers, [other]);
+
+        for _ in 1..MAX_REFERRERS {
+            exchange.set_referrer(Pubkey::new_unique(), true).unwrap();
+        }
+        assert_eq!(exchange.set_referrer(referrer, true), Err(ProgramError::AccountDataTooSmall));
+        // removing one on a full list still works
+        exchange.set_referrer(other, false).unwrap();
+        assert_eq!(exchange.state().referrers.len(), MAX_REFERRERS - 1);
+    }
+
+    #[test]
+    fn set_fee_tiers_checks_order_and_fees() {
+        let mut exchange = Exchange::new();
+        let tier = |min_volume_usd, fee_bps| FeeTier { min_volume_usd, fee_bps };
+        let tiers = vec![tier(1_000, 25), tier(10_000, 20)];
+        exchange.set_fee_tiers(tiers.clone()).unwrap();
+        assert_eq!(exchange.state().fee_tiers, tiers);
+
+        for bad_tiers in [
+            vec![tier(10_000, 20), tier(1_000, 25)],
+            vec![tier(1_000, 25), tier(1_000, 20)],
+            vec![tier(1_000, MAX_FEE_BPS + 1)],
+            vec![tier(0, 0); MAX_FEE_TIERS + 1],
+        ] {
+            assert!(exchange.set_fee_tiers(bad_tiers).is_err());
+        }
+        assert_eq!(exchange.state().fee_tiers, tiers);
+
+        exchange.set_fee_tiers(Vec::new()).unwrap();
+        assert!(exchange.state().fee_tiers.is_empty());
+    }
+
+    #[test]
+    fn role_holder_must_sign() {
+        let mut exchange = Exchange::new();
+        let admin = exchange.admin;
+        assert_eq!(
+            exchange.set_role(&admin, false, Role::Operator, Pubkey::new_unique()),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        assert_eq!(exchange.state().operator, admin);
+    }
+
+    #[test]
+    fn rejects_signer_without_role() {
+        let mut exchange = Exchange::new();
+        let admin = exchange.admin;
+        let intruder = Pubkey::new_unique();
+        assert_eq!(
+            exchange.set_role(&intruder, true, Role::Admin, intruder),
+            Err(TokenError::Unauthorized.into())
+        );
+        assert_eq!(exchange.state().admin, admin);
+    }
+
+    #[test]
+    fn set_role_hands_role_over() {
+        let mut exchange = Exchange::new();
+        let old_admin = exchange.admin;
+        let (operator, new_admin) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        exchange.set_role(&old_admin, true, Role::Operator, operator).unwrap();
+        let state = exchange.state();
+        assert_eq!((state.admin, state.operator, state.pauser), (old_admin, operator, old_admin));
+        // operator does not get admin rights
+        assert_eq!(
+            exchange.set_role(&operator, true, Role::Pauser, operator),
+            Err(TokenError::Unauthorized.into())
+        );
+
+        exchange.set_role(&old_admin, true, Role::Admin, new_admin).unwrap();
+        assert_eq!(
+            exchange.set_role(&old_admin, true, Role::Admin, old_admin),
+            Err(TokenError::Unauthorized.into())
+        );
+        exchange.set_role(&new_admin, true, Role::Pauser, operator).unwrap();
+        assert_eq!(exchange.state().pauser, operator);
+    }
 }
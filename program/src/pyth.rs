@@ -0,0 +1,206 @@
+use solana_program::{
+    account_info::AccountInfo,
+    msg,
+    program_error::ProgramError,
+    pubkey,
+    pubkey::Pubkey,
+};
+use crate::error::TokenError;
+use crate::oracle::PriceSource;
+
+// Reader of Pyth v2 price accounts. The account is parsed in place, offsets follow
+// `pc_price_t` of pyth-client, only the aggregate price is used.
+
+// oracle programs owning the price accounts on mainnet-beta and devnet
+pub const PROGRAM_IDS: [Pubkey; 2] = [
+    pubkey!("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH"),
+    pubkey!("gSbePebfvPy7tRqimPoVecS2UsBvYv46ynrzWocc92s"),
+];
+
+pub const MAGIC: u32 = 0xa1b2_c3d4;
+pub const VERSION: u32 = 2;
+pub const ACCOUNT_TYPE_PRICE: u32 = 3;
+pub const PRICE_TYPE_PRICE: u32 = 1;
+pub const STATUS_TRADING: u32 = 1;
+// header and aggregate, publisher components that follow are not read
+pub const PRICE_ACCOUNT_MIN_LEN: usize = 240;
+
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 4;
+const ACCOUNT_TYPE_OFFSET: usize = 8;
+const PRICE_TYPE_OFFSET: usize = 16;
+const EXPONENT_OFFSET: usize = 20;
+const TIMESTAMP_OFFSET: usize = 96;
+const AGG_PRICE_OFFSET: usize = 208;
+const AGG_CONF_OFFSET: usize = 216;
+const AGG_STATUS_OFFSET: usize = 224;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PythPrice {
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+}
+
+impl PythPrice {
+    pub fn read(data_feed_account: &AccountInfo) -> Result<Self, ProgramError> {
+        if !PROGRAM_IDS.contains(data_feed_account.owner) {
+            msg!("Data feed {} is not owned by Pyth program", data_feed_account.key);
+            return Err(TokenError::MismatchedAccountsError.into());
+        }
+        let price = Self::parse(&data_feed_account.data.borrow());
+        if price.is_err() {
+            msg!("Could not read Pyth feed {}", data_feed_account.key);
+        }
+        price
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < PRICE_ACCOUNT_MIN_LEN
+            || read_u32(data, MAGIC_OFFSET) != MAGIC
+            || read_u32(data, VERSION_OFFSET) != VERSION
+            || read_u32(data, ACCOUNT_TYPE_OFFSET) != ACCOUNT_TYPE_PRICE
+            || read_u32(data, PRICE_TYPE_OFFSET) != PRICE_TYPE_PRICE
+        {
+            msg!("Account is not a Pyth price account");
+            return Err(TokenError::OracleDataFeedError.into());
+        }
+        let status = read_u32(data, AGG_STATUS_OFFSET);
+        if status != STATUS_TRADING {
+            msg!("Pyth price status is {}, not trading", status);
+            return Err(TokenError::OraclePriceUnavailable.into());
+        }
+        Ok(PythPrice {
+            price: read_i64(data, AGG_PRICE_OFFSET),
+            conf: read_u64(data, AGG_CONF_OFFSET),
+            exponent: read_u32(data, EXPONENT_OFFSET) as i32,
+            publish_time: read_i64(data, TIMESTAMP_OFFSET),
+        })
+    }
+}
+
+impl PriceSource for PythPrice {
+    fn price(&self) -> i128 {
+        self.price as i128
+    }
+
+    fn exponent(&self) -> i32 {
+        self.exponent
+    }
+
+    fn confidence(&self) -> u128 {
+        self.conf as u128
+    }
+
+    fn publish_time(&self) -> i64 {
+        self.publish_time
+    }
+}
+
+// callers check the length first
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_i64(data: &[u8], offset: usize) -> i64 {
+    i64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oracle::checked_price;
+    use crate::pricing::Price;
+
+    // full size of a Pyth v2 price account with 32 publisher slots
+    const PRICE_ACCOUNT_LEN: usize = 3312;
+
+    fn price_account(price: i64, conf: u64, exponent: i32, status: u32, publish_time: i64) -> Vec<u8> {
+        let mut data = vec![0; PRICE_ACCOUNT_LEN];
+        let mut put = |offset: usize, bytes: &[u8]| data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        put(MAGIC_OFFSET, &MAGIC.to_le_bytes());
+        put(VERSION_OFFSET, &VERSION.to_le_bytes());
+        put(ACCOUNT_TYPE_OFFSET, &ACCOUNT_TYPE_PRICE.to_le_bytes());
+        put(12, &(PRICE_ACCOUNT_LEN as u32).to_le_bytes());
+        put(PRICE_TYPE_OFFSET, &PRICE_TYPE_PRICE.to_le_bytes());
+        put(EXPONENT_OFFSET, &exponent.to_le_bytes());
+        put(TIMESTAMP_OFFSET, &publish_time.to_le_bytes());
+        put(AGG_PRICE_OFFSET, &price.to_le_bytes());
+        put(AGG_CONF_OFFSET, &conf.to_le_bytes());
+        put(AGG_STATUS_OFFSET, &status.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn parses_price_account() {
+        let data = price_account(2_012_345_678, 1_500_000, -8, STATUS_TRADING, 1_700_000_000);
+        let price = PythPrice::parse(&data).unwrap();
+        assert_eq!(price, PythPrice {
+            price: 2_012_345_678,
+            conf: 1_500_000,
+            exponent: -8,
+            publish_time: 1_700_000_000,
+        });
+        assert_eq!(
+            checked_price(&price, 1_700_000_010, 60, 100),
+            Ok(Price { value: 2_012_345_678, decimals: 8 })
+        );
+    }
+
+    #[test]
+    fn rejects_other_accounts() {
+        let data = price_account(1, 0, -8, STATUS_TRADING, 0);
+        assert!(PythPrice::parse(&data[..PRICE_ACCOUNT_MIN_LEN]).is_ok());
+        assert_eq!(
+            PythPrice::parse(&data[..PRICE_ACCOUNT_MIN_LEN - 1]),
+            Err(TokenError::OracleDataFeedError.into())
+        );
+
+        for offset in [MAGIC_OFFSET, VERSION_OFFSET, ACCOUNT_TYPE_OFFSET, PRICE_TYPE_OFFSET] {
+            let mut data = data.clone();
+            data[offset] ^= 0xff;
+            assert_eq!(PythPrice::parse(&data), Err(TokenError::OracleDataFeedError.into()));
+        }
+    }
+
+    #[test]
+    fn reads_only_accounts_of_pyth_program() {
+        let key = Pubkey::new_unique();
+        let mut data = price_account(2_012_345_678, 0, -8, STATUS_TRADING, 0);
+        for owner in PROGRAM_IDS {
+            let mut lamports = 0;
+            let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+            assert!(PythPrice::read(&account).is_ok());
+        }
+
+        let (owner, mut lamports) = (Pubkey::new_unique(), 0);
+        let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        assert_eq!(PythPrice::read(&account), Err(TokenError::MismatchedAccountsError.into()));
+    }
+
+    #[test]
+    fn rejects_price_that_is_not_trading() {
+        // unknown, halted, auction
+        for status in [0, 2, 3] {
+            let data = price_account(2_012_345_678, 0, -8, status, 0);
+            assert_eq!(PythPrice::parse(&data), Err(TokenError::OraclePriceUnavailable.into()));
+        }
+    }
+
+    #[test]
+    fn honors_confidence_and_age() {
+        // confidence is exactly 1% of the price
+        let price = PythPrice::parse(&price_account(10_000, 100, -2, STATUS_TRADING, 1_000)).unwrap();
+        assert!(checked_price(&price, 1_000, 60, 100).is_ok());
+        assert_eq!(checked_price(&price, 1_000, 60, 99), Err(TokenError::OracleConfidenceTooWide.into()));
+        assert_eq!(checked_price(&price, 1_061, 60, 100), Err(TokenError::OracleStale.into()));
+
+        let price = PythPrice::parse(&price_account(-5, 0, -2, STATUS_TRADING, 1_000)).unwrap();
+        assert_eq!(checked_price(&price, 1_000, 60, 100), Err(TokenError::OracleInvalidPrice.into()));
+    }
+}
@@ -0,0 +1,877 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use crate::error::TokenError;
+
+pub const EXCHANGE_STATE_SEED: &[u8] = b"exchange_state";
+pub const TOKEN_CONFIG_SEED: &[u8] = b"token_config";
+pub const PAIR_CONFIG_SEED: &[u8] = b"pair_config";
+pub const MANUAL_PRICE_SEED: &[u8] = b"manual_price";
+pub const PRICE_HISTORY_SEED: &[u8] = b"price_history";
+pub const FEE_VAULT_SEED: &[u8] = b"fee_vault";
+pub const USER_STATS_SEED: &[u8] = b"user_stats";
+// max number of mints the exchange state account has room for
+pub const MAX_MINTS: usize = 32;
+
+// every program owned account starts with discriminator and layout version
+pub const ACCOUNT_HEADER_LEN: usize = 8 + 1;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum AccountType {
+    ExchangeState,
+    TokenConfig,
+    PairConfig,
+    ManualPrice,
+    PriceHistory,
+    UserStats,
+}
+
+// Layout changes must only append fields whose zero value is a sane default:
+// `upgrade` reads older versions by zero-extending them to the current size.
+// Anything else needs its own `upgrade` implementation.
+pub trait StateAccount: BorshSerialize + BorshDeserialize {
+    const DISCRIMINATOR: [u8; 8];
+    const VERSION: u8;
+    // account size, header included
+    const LEN: usize;
+
+    fn is_initialized(&self) -> bool;
+
+    // program derived address the account must live at
+    fn address(&self, program_id: &Pubkey) -> Result<Pubkey, ProgramError>;
+
+    fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < ACCOUNT_HEADER_LEN || data[..8] != Self::DISCRIMINATOR {
+            msg!("Account discriminator mismatch, legacy accounts have to be migrated");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let version = data[8];
+        if version < Self::VERSION {
+            msg!("Account layout v{} is outdated, current is v{}", version, Self::VERSION);
+            return Err(TokenError::AccountNeedsMigration.into());
+        }
+        if version > Self::VERSION {
+            msg!("Unknown account layout v{}", version);
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(Self::deserialize(&mut &data[ACCOUNT_HEADER_LEN..])?)
+    }
+
+    fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        if data.len() < Self::LEN {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        // zeroed tail is what lets newer versions read appended fields as defaults
+        data.fill(0);
+        data[..8].copy_from_slice(&Self::DISCRIMINATOR);
+        data[8] = Self::VERSION;
+        self.serialize(&mut &mut data[ACCOUNT_HEADER_LEN..])?;
+        Ok(())
+    }
+
+    // reads any older layout, v1 accounts have no header at all
+    fn upgrade(data: &[u8]) -> Result<Self, ProgramError> {
+        let payload = if data.len() >= ACCOUNT_HEADER_LEN && data[..8] == Self::DISCRIMINATOR {
+            if data[8] > Self::VERSION {
+                msg!("Unknown account layout v{}", data[8]);
+                return Err(ProgramError::InvalidAccountData);
+            }
+            &data[ACCOUNT_HEADER_LEN..]
+        } else {
+            data
+        };
+        let mut extended = payload.to_vec();
+        if extended.len() < Self::LEN - ACCOUNT_HEADER_LEN {
+            extended.resize(Self::LEN - ACCOUNT_HEADER_LEN, 0);
+        }
+        let account = Self::deserialize(&mut &extended[..])?;
+        if !account.is_initialized() {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        Ok(account)
+    }
+
+    fn load_account(program_id: &Pubkey, account: &AccountInfo) -> Result<Self, ProgramError> {
+        if account.owner != program_id {
+            msg!("Account {} is not owned by the program", account.key);
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let state = Self::unpack(&account.data.borrow())?;
+        if !state.is_initialized() {
+            msg!("Account {} is not initialized", account.key);
+            return Err(ProgramError::UninitializedAccount);
+        }
+        if state.address(program_id)? != *account.key {
+            msg!("Account {} address mismatch", account.key);
+            return Err(TokenError::MismatchedAccountsError.into());
+        }
+        Ok(state)
+    }
+
+    fn save(&self, account: &AccountInfo) -> ProgramResult {
+        self.pack(&mut account.data.borrow_mut())
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct RegisteredMint {
+    pub mint: Pubkey,
+    // token account (PDA) holding exchange funds of this mint
+    pub vault: Pubkey,
+    pub vault_seed: [u8; 16],
+    pub vault_bump: u8,
+}
+
+impl RegisteredMint {
+    pub const LEN: usize = 32 + 32 + 16 + 1;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    // manages roles, tokens and fees
+    Admin,
+    // moves funds between exchange wallet and vaults
+    Operator,
+    // can halt trading
+    Pauser,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct ExchangeState {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub exchange_wallet: Pubkey,
+    pub admin: Pubkey,
+    pub operator: Pubkey,
+    pub pauser: Pubkey,
+    // halts all swaps
+    pub paused: bool,
+    pub mints: Vec<RegisteredMint>,
+    // kept from the output of every swap, pairs may override it
+    pub fee_bps: u16,
+    // part of the fee paid to the referrer of a swap
+    pub referral_share_bps: u16,
+    // wallets whose token accounts may be passed as referrer
+    pub referrers: Vec<Pubkey>,
+    // by ascending volume, the highest one a client reached applies
+    pub fee_tiers: Vec<FeeTier>,
+}
+
+// fees above this would be a misconfiguration rather than a business decision
+pub const MAX_FEE_BPS: u16 = 1_000;
+pub const MAX_REFERRERS: usize = 16;
+pub const MAX_FEE_TIERS: usize = 8;
+
+// fee for clients with at least `min_volume_usd` traded over the last VOLUME_DAYS,
+// it only applies where it is lower than the pair's fee
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct FeeTier {
+    // scaled by USD_VOLUME_DECIMALS
+    pub min_volume_usd: u64,
+    pub fee_bps: u16,
+}
+
+impl FeeTier {
+    pub const LEN: usize = 8 + 2;
+}
+
+impl StateAccount for ExchangeState {
+    const DISCRIMINATOR: [u8; 8] = *b"exchange";
+    const VERSION: u8 = 5;
+    const LEN: usize = ACCOUNT_HEADER_LEN + 1 + 1 + 32 + 32 * 3 + 1 + 4 + MAX_MINTS * RegisteredMint::LEN + 2
+        + 2 + 4 + MAX_REFERRERS * 32 + 4 + MAX_FEE_TIERS * FeeTier::LEN;
+
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+
+    fn address(&self, program_id: &Pubkey) -> Result<Pubkey, ProgramError> {
+        Ok(Pubkey::create_program_address(
+            &[EXCHANGE_STATE_SEED, &[self.bump_seed]],
+            program_id
+        )?)
+    }
+}
+
+impl ExchangeState {
+    pub fn load(program_id: &Pubkey, account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::load_account(program_id, account)
+    }
+
+    pub fn role_authority(&self, role: Role) -> &Pubkey {
+        match role {
+            Role::Admin => &self.admin,
+            Role::Operator => &self.operator,
+            Role::Pauser => &self.pauser,
+        }
+    }
+
+    pub fn set_role_authority(&mut self, role: Role, authority: Pubkey) {
+        match role {
+            Role::Admin => self.admin = authority,
+            Role::Operator => self.operator = authority,
+            Role::Pauser => self.pauser = authority,
+        }
+    }
+
+    pub fn find_mint(&self, mint: &Pubkey) -> Option<&RegisteredMint> {
+        self.mints.iter().find(|registered| registered.mint == *mint)
+    }
+
+    pub fn is_referrer(&self, wallet: &Pubkey) -> bool {
+        self.referrers.contains(wallet)
+    }
+}
+
+// zero variant is what accounts created before providers existed use
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OracleProvider {
+    #[default]
+    Chainlink,
+    Pyth,
+    Switchboard,
+    // price_feed is the mint's ManualPrice account
+    Manual,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct OracleFeed {
+    pub provider: OracleProvider,
+    pub price_feed: Pubkey,
+}
+
+impl OracleFeed {
+    pub const LEN: usize = 1 + 32;
+}
+
+// feeds a mint can be priced with, the primary one included
+pub const MAX_PRICE_FEEDS: usize = 5;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct TokenConfig {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub mint: Pubkey,
+    pub decimals: u8,
+    // primary feed this mint is priced with
+    pub price_feed: Pubkey,
+    pub enabled: bool,
+    pub paused: bool,
+    // seconds, 0 means DEFAULT_MAX_PRICE_AGE
+    pub max_price_age: u32,
+    // how `price_feed` has to be read
+    pub oracle_provider: OracleProvider,
+    // max confidence interval relative to the price, 0 means DEFAULT_MAX_CONFIDENCE_BPS
+    pub max_confidence_bps: u16,
+    // seconds a manual price may stay valid, 0 means DEFAULT_MAX_MANUAL_PRICE_LIFETIME
+    pub max_manual_price_lifetime: u32,
+    // valid feeds a price needs, 0 means 1
+    pub min_valid_feeds: u8,
+    // feeds besides the primary one, the price is the median of all valid feeds
+    pub extra_feeds: Vec<OracleFeed>,
+    // token account (PDA) swap fees in this mint go to, default until it is initialized
+    pub fee_vault: Pubkey,
+    pub fee_vault_bump: u8,
+    // all fees ever collected in this mint, withdrawals do not reduce it
+    // and referral shares are not part of it
+    pub fees_collected: u64,
+    // all fees ever withdrawn from the fee vault
+    pub fees_withdrawn: u64,
+}
+
+pub const DEFAULT_MAX_PRICE_AGE: u32 = 60;
+pub const DEFAULT_MAX_CONFIDENCE_BPS: u16 = 100;
+pub const DEFAULT_MAX_MANUAL_PRICE_LIFETIME: u32 = 3_600;
+
+impl StateAccount for TokenConfig {
+    const DISCRIMINATOR: [u8; 8] = *b"tokencfg";
+    const VERSION: u8 = 9;
+    const LEN: usize = ACCOUNT_HEADER_LEN + 1 + 1 + 32 + 1 + 32 + 1 + 1 + 4 + 1 + 2 + 4 + 1
+        + 4 + (MAX_PRICE_FEEDS - 1) * OracleFeed::LEN + 32 + 1 + 8 + 8;
+
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+
+    fn address(&self, program_id: &Pubkey) -> Result<Pubkey, ProgramError> {
+        Ok(Pubkey::create_program_address(
+            &[TOKEN_CONFIG_SEED, self.mint.as_ref(), &[self.bump_seed]],
+            program_id
+        )?)
+    }
+}
+
+impl TokenConfig {
+    pub fn load(program_id: &Pubkey, account: &AccountInfo, mint: &Pubkey) -> Result<Self, ProgramError> {
+        let config = Self::load_account(program_id, account)?;
+        if config.mint != *mint {
+            msg!("Token config does not belong to this mint");
+            return Err(TokenError::MintMismatch.into());
+        }
+        Ok(config)
+    }
+
+    pub fn max_price_age(&self) -> u32 {
+        if self.max_price_age == 0 {
+            DEFAULT_MAX_PRICE_AGE
+        } else {
+            self.max_price_age
+        }
+    }
+
+    pub fn max_confidence_bps(&self) -> u16 {
+        if self.max_confidence_bps == 0 {
+            DEFAULT_MAX_CONFIDENCE_BPS
+        } else {
+            self.max_confidence_bps
+        }
+    }
+
+    pub fn max_manual_price_lifetime(&self) -> u32 {
+        if self.max_manual_price_lifetime == 0 {
+            DEFAULT_MAX_MANUAL_PRICE_LIFETIME
+        } else {
+            self.max_manual_price_lifetime
+        }
+    }
+
+    pub fn min_valid_feeds(&self) -> usize {
+        (self.min_valid_feeds as usize).max(1)
+    }
+
+    // primary feed first, then the extra ones in the order swaps pass their accounts
+    pub fn feeds(&self) -> impl Iterator<Item = OracleFeed> + '_ {
+        std::iter::once(OracleFeed { provider: self.oracle_provider, price_feed: self.price_feed })
+            .chain(self.extra_feeds.iter().cloned())
+    }
+}
+
+// zero variant is what pairs created before TWAP existed use
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum PriceMode {
+    #[default]
+    Spot,
+    // both tokens are priced at their TWAP over the pair's window
+    Twap,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct PairConfig {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    // set by the pauser or by the circuit breaker
+    pub paused: bool,
+    // max move of the pair price between two swaps, 0 disables the circuit breaker
+    pub max_price_change_bps: u16,
+    // base price in quote tokens, scaled by PAIR_PRICE_DECIMALS
+    pub last_price: u128,
+    pub price_mode: PriceMode,
+    // seconds, 0 means DEFAULT_TWAP_WINDOW
+    pub twap_window: u32,
+    // max distance of spot from TWAP of either token, 0 disables the check
+    pub max_twap_deviation_bps: u16,
+    // overrides ExchangeState::fee_bps, None uses it
+    pub fee_bps: Option<u16>,
+    // below the oracle price when the client sells the base token
+    pub bid_spread_bps: u16,
+    // above the oracle price when the client buys the base token
+    pub ask_spread_bps: u16,
+}
+
+pub const PAIR_PRICE_DECIMALS: u32 = 9;
+pub const DEFAULT_TWAP_WINDOW: u32 = 600;
+pub const MAX_SPREAD_BPS: u16 = 1_000;
+
+impl StateAccount for PairConfig {
+    const DISCRIMINATOR: [u8; 8] = *b"pair_cfg";
+    const VERSION: u8 = 5;
+    const LEN: usize = ACCOUNT_HEADER_LEN + 1 + 1 + 32 + 32 + 1 + 2 + 16 + 1 + 4 + 2 + 1 + 2 + 2 + 2;
+
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+
+    fn address(&self, program_id: &Pubkey) -> Result<Pubkey, ProgramError> {
+        Ok(Pubkey::create_program_address(
+            &[
+                PAIR_CONFIG_SEED,
+                self.base_mint.as_ref(),
+                self.quote_mint.as_ref(),
+                &[self.bump_seed]
+            ],
+            program_id
+        )?)
+    }
+}
+
+impl PairConfig {
+    pub fn load(program_id: &Pubkey, account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::load_account(program_id, account)
+    }
+
+    pub fn contains(&self, mint_a: &Pubkey, mint_b: &Pubkey) -> bool {
+        (self.base_mint == *mint_a && self.quote_mint == *mint_b)
+            || (self.base_mint == *mint_b && self.quote_mint == *mint_a)
+    }
+
+    pub fn twap_window(&self) -> u32 {
+        if self.twap_window == 0 {
+            DEFAULT_TWAP_WINDOW
+        } else {
+            self.twap_window
+        }
+    }
+
+    pub fn fee_bps(&self, exchange_state: &ExchangeState) -> u16 {
+        self.fee_bps.unwrap_or(exchange_state.fee_bps)
+    }
+}
+
+// price set by the operator for a mint without an oracle, `price * 10^exponent` USD
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct ManualPrice {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub mint: Pubkey,
+    pub price: u64,
+    pub exponent: i32,
+    pub updated_at: i64,
+    // unix timestamp after which the price must not be used
+    pub expires_at: i64,
+    pub updated_by: Pubkey,
+}
+
+impl StateAccount for ManualPrice {
+    const DISCRIMINATOR: [u8; 8] = *b"manprice";
+    const VERSION: u8 = 1;
+    const LEN: usize = ACCOUNT_HEADER_LEN + 1 + 1 + 32 + 8 + 4 + 8 + 8 + 32;
+
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+
+    fn address(&self, program_id: &Pubkey) -> Result<Pubkey, ProgramError> {
+        Ok(Pubkey::create_program_address(
+            &[MANUAL_PRICE_SEED, self.mint.as_ref(), &[self.bump_seed]],
+            program_id
+        )?)
+    }
+}
+
+impl ManualPrice {
+    pub fn load(program_id: &Pubkey, account: &AccountInfo, mint: &Pubkey) -> Result<Self, ProgramError> {
+        let manual_price = Self::load_account(program_id, account)?;
+        if manual_price.mint != *mint {
+            msg!("Manual price does not belong to this mint");
+            return Err(TokenError::MintMismatch.into());
+        }
+        Ok(manual_price)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct PriceObservation {
+    pub timestamp: i64,
+    // PriceHistory::cumulative_price at `timestamp`
+    pub cumulative_price: u128,
+}
+
+impl PriceObservation {
+    pub const LEN: usize = 8 + 16;
+}
+
+// observations kept per mint, at most one per PRICE_OBSERVATION_INTERVAL
+pub const MAX_PRICE_OBSERVATIONS: usize = 32;
+pub const PRICE_OBSERVATION_INTERVAL: i64 = 60;
+// decimals of the USD prices kept in the history
+pub const PRICE_HISTORY_DECIMALS: u8 = 9;
+
+// spot prices of a mint accumulated over time, see twap.rs
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct PriceHistory {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub mint: Pubkey,
+    // spot price at the last update, scaled by PRICE_HISTORY_DECIMALS
+    pub last_price: u128,
+    pub last_update: i64,
+    // sum of `last_price * seconds it was the spot price`, wraps around
+    pub cumulative_price: u128,
+    // ring buffer slot the next observation goes to
+    pub next_observation: u8,
+    pub observations: Vec<PriceObservation>,
+}
+
+impl StateAccount for PriceHistory {
+    const DISCRIMINATOR: [u8; 8] = *b"pricehst";
+    const VERSION: u8 = 1;
+    const LEN: usize = ACCOUNT_HEADER_LEN + 1 + 1 + 32 + 16 + 8 + 16 + 1
+        + 4 + MAX_PRICE_OBSERVATIONS * PriceObservation::LEN;
+
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+
+    fn address(&self, program_id: &Pubkey) -> Result<Pubkey, ProgramError> {
+        Ok(Pubkey::create_program_address(
+            &[PRICE_HISTORY_SEED, self.mint.as_ref(), &[self.bump_seed]],
+            program_id
+        )?)
+    }
+}
+
+impl PriceHistory {
+    pub fn load(program_id: &Pubkey, account: &AccountInfo, mint: &Pubkey) -> Result<Self, ProgramError> {
+        let price_history = Self::load_account(program_id, account)?;
+        if price_history.mint != *mint {
+            msg!("Price history does not belong to this mint");
+            return Err(TokenError::MintMismatch.into());
+        }
+        Ok(price_history)
+    }
+}
+
+// length of the rolling volume window, one bucket per day
+pub const VOLUME_DAYS: usize = 30;
+pub const SECONDS_PER_DAY: i64 = 86_400;
+// decimals of the USD volumes
+pub const USD_VOLUME_DECIMALS: u8 = 6;
+
+// swap volume of a client wallet, see volume.rs
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct UserStats {
+    pub is_initialized: bool,
+    pub bump_seed: u8,
+    pub owner: Pubkey,
+    // day (unix time / SECONDS_PER_DAY) of the last recorded swap
+    pub last_day: i64,
+    // USD volume of each day, scaled by USD_VOLUME_DECIMALS, day `d` is in slot `d % VOLUME_DAYS`
+    pub daily_volume: [u64; VOLUME_DAYS],
+    // all time USD volume, scaled by USD_VOLUME_DECIMALS
+    pub total_volume: u64,
+}
+
+impl StateAccount for UserStats {
+    const DISCRIMINATOR: [u8; 8] = *b"usrstats";
+    const VERSION: u8 = 1;
+    const LEN: usize = ACCOUNT_HEADER_LEN + 1 + 1 + 32 + 8 + VOLUME_DAYS * 8 + 8;
+
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+
+    fn address(&self, program_id: &Pubkey) -> Result<Pubkey, ProgramError> {
+        Ok(Pubkey::create_program_address(
+            &[USER_STATS_SEED, self.owner.as_ref(), &[self.bump_seed]],
+            program_id
+        )?)
+    }
+}
+
+impl UserStats {
+    pub fn load(program_id: &Pubkey, account: &AccountInfo, owner: &Pubkey) -> Result<Self, ProgramError> {
+        let user_stats = Self::load_account(program_id, account)?;
+        if user_stats.owner != *owner {
+            msg!("User stats do not belong to {}", owner);
+            return Err(TokenError::MismatchedAccountsError.into());
+        }
+        Ok(user_stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // layouts as they were stored before accounts got a header
+
+    #[derive(BorshSerialize)]
+    struct ExchangeStateV1 {
+        is_initialized: bool,
+        bump_seed: u8,
+        exchange_wallet: Pubkey,
+        admin: Pubkey,
+        operator: Pubkey,
+        pauser: Pubkey,
+        paused: bool,
+        mints: Vec<RegisteredMint>,
+    }
+
+    const EXCHANGE_STATE_V1_LEN: usize = 1 + 1 + 32 + 32 * 3 + 1 + 4 + MAX_MINTS * RegisteredMint::LEN;
+
+    #[derive(BorshSerialize)]
+    struct TokenConfigV1 {
+        is_initialized: bool,
+        bump_seed: u8,
+        mint: Pubkey,
+        decimals: u8,
+        price_feed: Pubkey,
+        enabled: bool,
+        paused: bool,
+    }
+
+    const TOKEN_CONFIG_V1_LEN: usize = 1 + 1 + 32 + 1 + 32 + 1 + 1;
+
+    #[derive(BorshSerialize)]
+    struct PairConfigV1 {
+        is_initialized: bool,
+        bump_seed: u8,
+        base_mint: Pubkey,
+        quote_mint: Pubkey,
+        paused: bool,
+        max_price_change_bps: u16,
+        last_price: u128,
+    }
+
+    const PAIR_CONFIG_V1_LEN: usize = 1 + 1 + 32 + 32 + 1 + 2 + 16;
+
+    fn v1_account_data<T: BorshSerialize>(account: &T, len: usize) -> Vec<u8> {
+        let mut data = vec![0; len];
+        account.serialize(&mut &mut data[..]).unwrap();
+        data
+    }
+
+    fn migrate<T: StateAccount>(v1_data: &[u8]) -> (T, Vec<u8>) {
+        let account = T::upgrade(v1_data).unwrap();
+        let mut data = vec![0; T::LEN];
+        account.pack(&mut data).unwrap();
+        (account, data)
+    }
+
+    #[test]
+    fn exchange_state_v1_to_v2() {
+        let v1 = ExchangeStateV1 {
+            is_initialized: true,
+            bump_seed: 254,
+            exchange_wallet: Pubkey::new_unique(),
+            admin: Pubkey::new_unique(),
+            operator: Pubkey::new_unique(),
+            pauser: Pubkey::new_unique(),
+            paused: true,
+            mints: vec![
+                RegisteredMint {
+                    mint: Pubkey::new_unique(),
+                    vault: Pubkey::new_unique(),
+                    vault_seed: *b"toKeNpDaSeEd0000",
+                    vault_bump: 255,
+                },
+                RegisteredMint {
+                    mint: Pubkey::new_unique(),
+                    vault: Pubkey::new_unique(),
+                    vault_seed: [7; 16],
+                    vault_bump: 253,
+                },
+            ],
+        };
+        let v1_data = v1_account_data(&v1, EXCHANGE_STATE_V1_LEN);
+        assert!(ExchangeState::unpack(&v1_data).is_err());
+
+        let (state, data) = migrate::<ExchangeState>(&v1_data);
+        assert_eq!(&data[..8], b"exchange");
+        assert_eq!(data[8], ExchangeState::VERSION);
+        assert_eq!(state.bump_seed, v1.bump_seed);
+        assert_eq!(state.exchange_wallet, v1.exchange_wallet);
+        assert_eq!(state.admin, v1.admin);
+        assert_eq!(state.operator, v1.operator);
+        assert_eq!(state.pauser, v1.pauser);
+        assert!(state.paused);
+        assert_eq!(state.mints, v1.mints);
+        assert_eq!(state.fee_bps, 0);
+        assert_eq!(state.referral_share_bps, 0);
+        assert!(state.referrers.is_empty());
+        assert!(state.fee_tiers.is_empty());
+        assert_eq!(ExchangeState::unpack(&data).unwrap(), state);
+    }
+
+    #[test]
+    fn token_config_v1_to_v2() {
+        let v1 = TokenConfigV1 {
+            is_initialized: true,
+            bump_seed: 251,
+            mint: Pubkey::new_unique(),
+            decimals: 6,
+            price_feed: Pubkey::new_unique(),
+            enabled: true,
+            paused: false,
+        };
+        let v1_data = v1_account_data(&v1, TOKEN_CONFIG_V1_LEN);
+        assert!(TokenConfig::unpack(&v1_data).is_err());
+
+        let (config, data) = migrate::<TokenConfig>(&v1_data);
+        assert_eq!(&data[..8], b"tokencfg");
+        assert_eq!(data[8], TokenConfig::VERSION);
+        assert_eq!(config.bump_seed, v1.bump_seed);
+        assert_eq!(config.mint, v1.mint);
+        assert_eq!(config.decimals, v1.decimals);
+        assert_eq!(config.price_feed, v1.price_feed);
+        assert!(config.enabled);
+        assert!(!config.paused);
+        assert_eq!(config.max_price_age, 0);
+        assert_eq!(TokenConfig::unpack(&data).unwrap(), config);
+    }
+
+    #[test]
+    fn token_config_v2_to_v3() {
+        let v2 = TokenConfigV1 {
+            is_initialized: true,
+            bump_seed: 249,
+            mint: Pubkey::new_unique(),
+            decimals: 9,
+            price_feed: Pubkey::new_unique(),
+            enabled: false,
+            paused: true,
+        };
+        // v2 is the v1 layout behind a header
+        let mut v2_data = b"tokencfg".to_vec();
+        v2_data.push(2);
+        v2_data.extend(v1_account_data(&v2, TOKEN_CONFIG_V1_LEN));
+        assert_eq!(TokenConfig::unpack(&v2_data), Err(TokenError::AccountNeedsMigration.into()));
+
+        let (config, data) = migrate::<TokenConfig>(&v2_data);
+        assert_eq!(data[8], TokenConfig::VERSION);
+        assert_eq!(config.mint, v2.mint);
+        assert_eq!(config.price_feed, v2.price_feed);
+        assert!(!config.enabled);
+        assert!(config.paused);
+        assert_eq!(config.max_price_age, 0);
+        assert_eq!(config.max_price_age(), DEFAULT_MAX_PRICE_AGE);
+        assert_eq!(config.oracle_provider, OracleProvider::Chainlink);
+        assert_eq!(config.max_confidence_bps(), DEFAULT_MAX_CONFIDENCE_BPS);
+        assert_eq!(config.max_manual_price_lifetime(), DEFAULT_MAX_MANUAL_PRICE_LIFETIME);
+        assert_eq!(config.feeds().collect::<Vec<_>>(), vec![OracleFeed {
+            provider: OracleProvider::Chainlink,
+            price_feed: v2.price_feed,
+        }]);
+        assert_eq!(config.min_valid_feeds(), 1);
+        assert_eq!(config.fee_vault, Pubkey::default());
+        assert_eq!(config.fees_collected, 0);
+        assert_eq!(config.fees_withdrawn, 0);
+        assert_eq!(TokenConfig::unpack(&data).unwrap(), config);
+    }
+
+    #[test]
+    fn token_config_v8_to_v9() {
+        let config = TokenConfig {
+            is_initialized: true,
+            mint: Pubkey::new_unique(),
+            fee_vault: Pubkey::new_unique(),
+            fee_vault_bump: 252,
+            fees_collected: 12_345,
+            ..TokenConfig::default()
+        };
+        // v8 is the v9 layout without fees_withdrawn
+        let mut v8_data = vec![0; TokenConfig::LEN];
+        config.pack(&mut v8_data).unwrap();
+        v8_data[8] = 8;
+        v8_data.truncate(TokenConfig::LEN - 8);
+        assert_eq!(TokenConfig::unpack(&v8_data), Err(TokenError::AccountNeedsMigration.into()));
+
+        let (migrated, data) = migrate::<TokenConfig>(&v8_data);
+        assert_eq!(data[8], TokenConfig::VERSION);
+        assert_eq!(migrated, config);
+        assert_eq!(migrated.fees_withdrawn, 0);
+        assert_eq!(TokenConfig::unpack(&data).unwrap(), config);
+    }
+
+    #[test]
+    fn token_config_fits_all_feeds() {
+        let config = TokenConfig {
+            is_initialized: true,
+            extra_feeds: vec![
+                OracleFeed { provider: OracleProvider::Switchboard, price_feed: Pubkey::new_unique() };
+                MAX_PRICE_FEEDS - 1
+            ],
+            fee_vault: Pubkey::new_unique(),
+            fees_collected: u64::MAX,
+            fees_withdrawn: u64::MAX,
+            ..TokenConfig::default()
+        };
+        let mut data = vec![0; TokenConfig::LEN];
+        config.pack(&mut data).unwrap();
+        assert_eq!(TokenConfig::unpack(&data).unwrap(), config);
+    }
+
+    #[test]
+    fn exchange_state_fits_all_entries() {
+        let state = ExchangeState {
+            is_initialized: true,
+            mints: vec![
+                RegisteredMint { mint: Pubkey::new_unique(), vault: Pubkey::new_unique(), vault_seed: [7; 16], vault_bump: 255 };
+                MAX_MINTS
+            ],
+            referrers: vec![Pubkey::new_unique(); MAX_REFERRERS],
+            fee_tiers: vec![FeeTier { min_volume_usd: u64::MAX, fee_bps: MAX_FEE_BPS }; MAX_FEE_TIERS],
+            ..ExchangeState::default()
+        };
+        let mut data = vec![0; ExchangeState::LEN];
+        state.pack(&mut data).unwrap();
+        assert_eq!(ExchangeState::unpack(&data).unwrap(), state);
+    }
+
+    #[test]
+    fn pair_config_v1_to_v2() {
+        let v1 = PairConfigV1 {
+            is_initialized: true,
+            bump_seed: 250,
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            paused: false,
+            max_price_change_bps: 500,
+            last_price: 21_450_000_000,
+        };
+        let v1_data = v1_account_data(&v1, PAIR_CONFIG_V1_LEN);
+        assert!(PairConfig::unpack(&v1_data).is_err());
+
+        let (config, data) = migrate::<PairConfig>(&v1_data);
+        assert_eq!(&data[..8], b"pair_cfg");
+        assert_eq!(data[8], PairConfig::VERSION);
+        assert_eq!(config.base_mint, v1.base_mint);
+        assert_eq!(config.quote_mint, v1.quote_mint);
+        assert_eq!(config.max_price_change_bps, v1.max_price_change_bps);
+        assert_eq!(config.last_price, v1.last_price);
+        assert_eq!(config.price_mode, PriceMode::Spot);
+        assert_eq!(config.twap_window(), DEFAULT_TWAP_WINDOW);
+        assert_eq!(config.max_twap_deviation_bps, 0);
+        assert_eq!(config.fee_bps, None);
+        assert_eq!((config.bid_spread_bps, config.ask_spread_bps), (0, 0));
+        assert_eq!(PairConfig::unpack(&data).unwrap(), config);
+    }
+
+    #[test]
+    fn current_version_upgrade_is_noop() {
+        let config = TokenConfig {
+            is_initialized: true,
+            mint: Pubkey::new_unique(),
+            price_feed: Pubkey::new_unique(),
+            ..TokenConfig::default()
+        };
+        let mut data = vec![0; TokenConfig::LEN];
+        config.pack(&mut data).unwrap();
+        assert_eq!(TokenConfig::upgrade(&data).unwrap(), config);
+    }
+
+    #[test]
+    fn rejects_wrong_discriminator_and_newer_version() {
+        let config = PairConfig { is_initialized: true, ..PairConfig::default() };
+        let mut data = vec![0; PairConfig::LEN];
+        config.pack(&mut data).unwrap();
+        assert_eq!(TokenConfig::unpack(&data), Err(ProgramError::InvalidAccountData));
+
+        data[8] = PairConfig::VERSION + 1;
+        assert_eq!(PairConfig::unpack(&data), Err(ProgramError::InvalidAccountData));
+        assert_eq!(PairConfig::upgrade(&data), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn rejects_uninitialized_v1_account() {
+        let data = vec![0; TOKEN_CONFIG_V1_LEN];
+        assert_eq!(TokenConfig::upgrade(&data), Err(ProgramError::UninitializedAccount));
+    }
+}
@@ -0,0 +1,236 @@
+use solana_program::{
+    account_info::AccountInfo,
+    msg,
+    program_error::ProgramError,
+    pubkey,
+    pubkey::Pubkey,
+};
+use crate::error::TokenError;
+use crate::oracle::PriceSource;
+use crate::pricing::{mul_div, pow10, Rounding};
+
+// Reader of Switchboard v2 aggregator accounts. The account is parsed in place, offsets
+// follow the packed `AggregatorAccountData` of switchboard-v2, only the latest confirmed
+// round is used.
+
+// switchboard-v2 program, same address on mainnet-beta and devnet
+pub const PROGRAM_ID: Pubkey = pubkey!("SW1TCH7qEPTdLsDHRgPuMQjbQxKdH2aBStViMFnt64f");
+
+pub const DISCRIMINATOR: [u8; 8] = [217, 230, 65, 101, 201, 162, 27, 125];
+// everything up to the end of the latest confirmed round's std deviation
+pub const AGGREGATOR_MIN_LEN: usize = 406;
+
+const MIN_ORACLE_RESULTS_OFFSET: usize = 236;
+const LATEST_ROUND_OFFSET: usize = 341;
+const NUM_SUCCESS_OFFSET: usize = LATEST_ROUND_OFFSET;
+const ROUND_OPEN_TIMESTAMP_OFFSET: usize = LATEST_ROUND_OFFSET + 17;
+const RESULT_OFFSET: usize = LATEST_ROUND_OFFSET + 25;
+const STD_DEVIATION_OFFSET: usize = LATEST_ROUND_OFFSET + 45;
+
+// `mantissa * 10^-scale`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SwitchboardDecimal {
+    pub mantissa: i128,
+    pub scale: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SwitchboardPrice {
+    pub result: SwitchboardDecimal,
+    pub std_deviation: SwitchboardDecimal,
+    pub round_open_timestamp: i64,
+}
+
+impl SwitchboardPrice {
+    pub fn read(data_feed_account: &AccountInfo) -> Result<Self, ProgramError> {
+        if *data_feed_account.owner != PROGRAM_ID {
+            msg!("Data feed {} is not owned by Switchboard program", data_feed_account.key);
+            return Err(TokenError::MismatchedAccountsError.into());
+        }
+        let price = Self::parse(&data_feed_account.data.borrow());
+        if price.is_err() {
+            msg!("Could not read Switchboard aggregator {}", data_feed_account.key);
+        }
+        price
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < AGGREGATOR_MIN_LEN || data[..8] != DISCRIMINATOR {
+            msg!("Account is not a Switchboard aggregator");
+            return Err(TokenError::OracleDataFeedError.into());
+        }
+        // same rule the aggregator itself applies before reporting a result
+        let num_success = read_u32(data, NUM_SUCCESS_OFFSET);
+        let min_oracle_results = read_u32(data, MIN_ORACLE_RESULTS_OFFSET);
+        if num_success == 0 || num_success < min_oracle_results {
+            msg!("Switchboard round has {} of {} oracle results", num_success, min_oracle_results);
+            return Err(TokenError::OraclePriceUnavailable.into());
+        }
+        Ok(SwitchboardPrice {
+            result: read_decimal(data, RESULT_OFFSET),
+            std_deviation: read_decimal(data, STD_DEVIATION_OFFSET),
+            round_open_timestamp: read_i64(data, ROUND_OPEN_TIMESTAMP_OFFSET),
+        })
+    }
+}
+
+impl PriceSource for SwitchboardPrice {
+    fn price(&self) -> i128 {
+        self.result.mantissa
+    }
+
+    fn exponent(&self) -> i32 {
+        -(self.result.scale.min(i32::MAX as u32) as i32)
+    }
+
+    // std deviation rescaled to the result, rounded up; saturates instead of failing
+    // so that an absurd deviation is rejected as too wide
+    fn confidence(&self) -> u128 {
+        let deviation = self.std_deviation.mantissa.unsigned_abs();
+        let (result_scale, deviation_scale) = (self.result.scale, self.std_deviation.scale);
+        if deviation_scale >= result_scale {
+            match pow10(deviation_scale - result_scale) {
+                Ok(divisor) => mul_div(deviation, 1, divisor, Rounding::Up).unwrap_or(u128::MAX),
+                Err(_) => (deviation != 0) as u128,
+            }
+        } else {
+            match pow10(result_scale - deviation_scale) {
+                Ok(factor) => deviation.saturating_mul(factor),
+                Err(_) => u128::MAX,
+            }
+        }
+    }
+
+    fn publish_time(&self) -> i64 {
+        self.round_open_timestamp
+    }
+}
+
+// callers check the length first
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_i64(data: &[u8], offset: usize) -> i64 {
+    i64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_decimal(data: &[u8], offset: usize) -> SwitchboardDecimal {
+    SwitchboardDecimal {
+        mantissa: i128::from_le_bytes(data[offset..offset + 16].try_into().unwrap()),
+        scale: read_u32(data, offset + 16),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oracle::checked_price;
+    use crate::pricing::Price;
+
+    // size of the whole aggregator account, job and history fields are left zeroed
+    const AGGREGATOR_LEN: usize = 3851;
+
+    fn aggregator(
+        result: SwitchboardDecimal,
+        std_deviation: SwitchboardDecimal,
+        num_success: u32,
+        min_oracle_results: u32,
+        round_open_timestamp: i64
+    ) -> Vec<u8> {
+        let mut data = vec![0; AGGREGATOR_LEN];
+        let mut put = |offset: usize, bytes: &[u8]| data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        put(0, &DISCRIMINATOR);
+        put(MIN_ORACLE_RESULTS_OFFSET, &min_oracle_results.to_le_bytes());
+        put(NUM_SUCCESS_OFFSET, &num_success.to_le_bytes());
+        put(ROUND_OPEN_TIMESTAMP_OFFSET, &round_open_timestamp.to_le_bytes());
+        put(RESULT_OFFSET, &result.mantissa.to_le_bytes());
+        put(RESULT_OFFSET + 16, &result.scale.to_le_bytes());
+        put(STD_DEVIATION_OFFSET, &std_deviation.mantissa.to_le_bytes());
+        put(STD_DEVIATION_OFFSET + 16, &std_deviation.scale.to_le_bytes());
+        data
+    }
+
+    fn decimal(mantissa: i128, scale: u32) -> SwitchboardDecimal {
+        SwitchboardDecimal { mantissa, scale }
+    }
+
+    #[test]
+    fn parses_aggregator() {
+        let data = aggregator(decimal(1_234_567, 6), decimal(25, 4), 3, 2, 1_700_000_000);
+        let price = SwitchboardPrice::parse(&data).unwrap();
+        assert_eq!(price.result, decimal(1_234_567, 6));
+        assert_eq!(price.exponent(), -6);
+        assert_eq!(price.publish_time(), 1_700_000_000);
+        // 0.0025 at scale 6
+        assert_eq!(price.confidence(), 2_500);
+        assert_eq!(
+            checked_price(&price, 1_700_000_030, 60, 100),
+            Ok(Price { value: 1_234_567, decimals: 6 })
+        );
+    }
+
+    #[test]
+    fn rejects_other_accounts() {
+        let data = aggregator(decimal(1, 0), decimal(0, 0), 1, 1, 0);
+        assert!(SwitchboardPrice::parse(&data[..AGGREGATOR_MIN_LEN]).is_ok());
+        assert_eq!(
+            SwitchboardPrice::parse(&data[..AGGREGATOR_MIN_LEN - 1]),
+            Err(TokenError::OracleDataFeedError.into())
+        );
+
+        let mut data = data;
+        data[0] ^= 0xff;
+        assert_eq!(SwitchboardPrice::parse(&data), Err(TokenError::OracleDataFeedError.into()));
+    }
+
+    #[test]
+    fn reads_only_accounts_of_switchboard_program() {
+        let key = Pubkey::new_unique();
+        let mut data = aggregator(decimal(1_234_567, 6), decimal(0, 0), 1, 1, 0);
+        let mut lamports = 0;
+        let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &PROGRAM_ID, false, 0);
+        assert!(SwitchboardPrice::read(&account).is_ok());
+
+        let (owner, mut lamports) = (Pubkey::new_unique(), 0);
+        let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        assert_eq!(SwitchboardPrice::read(&account), Err(TokenError::MismatchedAccountsError.into()));
+    }
+
+    #[test]
+    fn rejects_round_without_enough_results() {
+        let data = aggregator(decimal(1_000, 3), decimal(0, 0), 1, 2, 0);
+        assert_eq!(SwitchboardPrice::parse(&data), Err(TokenError::OraclePriceUnavailable.into()));
+
+        let data = aggregator(decimal(1_000, 3), decimal(0, 0), 0, 0, 0);
+        assert_eq!(SwitchboardPrice::parse(&data), Err(TokenError::OraclePriceUnavailable.into()));
+    }
+
+    #[test]
+    fn confidence_is_rescaled_to_result() {
+        let price = |result_scale, deviation: SwitchboardDecimal| SwitchboardPrice {
+            result: decimal(1, result_scale),
+            std_deviation: deviation,
+            round_open_timestamp: 0,
+        };
+        assert_eq!(price(2, decimal(15, 3)).confidence(), 2);
+        assert_eq!(price(2, decimal(10, 3)).confidence(), 1);
+        assert_eq!(price(4, decimal(-15, 3)).confidence(), 150);
+        assert_eq!(price(2, decimal(0, 3)).confidence(), 0);
+        assert_eq!(price(60, decimal(1, 0)).confidence(), u128::MAX);
+        assert_eq!(price(0, decimal(1, 60)).confidence(), 1);
+    }
+
+    #[test]
+    fn applies_staleness_and_validity_rules() {
+        let data = aggregator(decimal(10_000, 2), decimal(100, 2), 1, 1, 1_000);
+        let price = SwitchboardPrice::parse(&data).unwrap();
+        assert!(checked_price(&price, 1_060, 60, 100).is_ok());
+        assert_eq!(checked_price(&price, 1_061, 60, 100), Err(TokenError::OracleStale.into()));
+        assert_eq!(checked_price(&price, 1_000, 60, 99), Err(TokenError::OracleConfidenceTooWide.into()));
+
+        let data = aggregator(decimal(0, 2), decimal(0, 2), 1, 1, 1_000);
+        let price = SwitchboardPrice::parse(&data).unwrap();
+        assert_eq!(checked_price(&price, 1_000, 60, 100), Err(TokenError::OracleInvalidPrice.into()));
+    }
+}
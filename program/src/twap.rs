@@ -0,0 +1,160 @@
+use solana_program::{
+    msg,
+    program_error::ProgramError,
+};
+use crate::error::TokenError;
+use crate::pricing::Price;
+use crate::state::{
+    PriceHistory, PriceObservation, MAX_PRICE_OBSERVATIONS, PRICE_HISTORY_DECIMALS, PRICE_OBSERVATION_INTERVAL
+};
+
+// Time weighted average prices. Every update adds the previous spot price times the seconds
+// it was in effect to a cumulative sum, snapshots of the sum are kept in a ring buffer and
+// the TWAP since a snapshot is the growth of the sum divided by the elapsed time.
+// A price only starts counting once it is recorded, so a spot price pushed around within
+// one transaction has no effect on the TWAP the same transaction sees.
+
+impl PriceHistory {
+    // cumulative price at `now`, assuming the last price still holds
+    fn cumulative_price_at(&self, now: i64) -> u128 {
+        let elapsed = now.saturating_sub(self.last_update).max(0) as u128;
+        self.cumulative_price.wrapping_add(self.last_price.wrapping_mul(elapsed))
+    }
+
+    pub fn record(&mut self, price: &Price, now: i64) -> Result<(), ProgramError> {
+        let price = price.rescale(PRICE_HISTORY_DECIMALS)?;
+        // cluster time may step back a little, it never rewinds the history
+        let now = now.max(self.last_update);
+        self.cumulative_price = self.cumulative_price_at(now);
+        self.last_price = price;
+        self.last_update = now;
+
+        let observation_due = match self.observations.iter().map(|observation| observation.timestamp).max() {
+            Some(latest) => now - latest >= PRICE_OBSERVATION_INTERVAL,
+            None => true,
+        };
+        if observation_due {
+            let observation = PriceObservation { timestamp: now, cumulative_price: self.cumulative_price };
+            let slot = self.next_observation as usize % MAX_PRICE_OBSERVATIONS;
+            if slot < self.observations.len() {
+                self.observations[slot] = observation;
+            } else {
+                self.observations.push(observation);
+            }
+            self.next_observation = ((slot + 1) % MAX_PRICE_OBSERVATIONS) as u8;
+        }
+        Ok(())
+    }
+
+    // average since the newest observation that is at least `window` seconds old
+    pub fn twap(&self, now: i64, window: u32) -> Result<Price, ProgramError> {
+        let start = now.saturating_sub(window as i64);
+        let observation = self.observations
+            .iter()
+            .filter(|observation| observation.timestamp <= start)
+            .max_by_key(|observation| observation.timestamp);
+        let observation = match observation {
+            Some(observation) if observation.timestamp < now => observation,
+            _ => {
+                msg!("Price history of {} does not cover {} seconds", self.mint, window);
+                return Err(TokenError::TwapUnavailable.into());
+            }
+        };
+        let elapsed = (now - observation.timestamp) as u128;
+        let value = self.cumulative_price_at(now).wrapping_sub(observation.cumulative_price) / elapsed;
+        if value == 0 {
+            msg!("Price history of {} has no price yet", self.mint);
+            return Err(TokenError::TwapUnavailable.into());
+        }
+        Ok(Price { value, decimals: PRICE_HISTORY_DECIMALS })
+    }
+}
+
+// rejects a spot price more than `max_deviation_bps` away from the TWAP
+pub fn check_deviation(spot: &Price, twap: &Price, max_deviation_bps: u16) -> Result<(), ProgramError> {
+    let spot_value = spot.rescale(twap.decimals)?;
+    let deviation_bps = spot_value.abs_diff(twap.value).checked_mul(10_000).ok_or(TokenError::Overflow)?;
+    let max_bps = twap.value.checked_mul(max_deviation_bps as u128).ok_or(TokenError::Overflow)?;
+    if deviation_bps > max_bps {
+        msg!("Spot price {} is more than {} bps away from TWAP {}", spot, max_deviation_bps, twap);
+        return Err(TokenError::PriceDeviatesFromTwap.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usd(value: u128) -> Price {
+        Price { value, decimals: 0 }
+    }
+
+    fn scaled(value: u128) -> Price {
+        Price { value: value * 1_000_000_000, decimals: PRICE_HISTORY_DECIMALS }
+    }
+
+    #[test]
+    fn twap_weights_prices_by_time() {
+        let mut history = PriceHistory::default();
+        history.record(&usd(10), 1_000).unwrap();
+        history.record(&usd(20), 1_300).unwrap();
+        // ten times the price for a second barely moves the average
+        history.record(&usd(200), 1_599).unwrap();
+        history.record(&usd(20), 1_600).unwrap();
+
+        // 300 s at 10, 299 s at 20, 1 s at 200
+        assert_eq!(history.twap(1_600, 600), Ok(Price { value: 15_300_000_000, decimals: PRICE_HISTORY_DECIMALS }));
+        // last price holds until the next update, the window starts at the observation of 1_300
+        assert_eq!(history.twap(1_900, 600), Ok(Price { value: 20_300_000_000, decimals: PRICE_HISTORY_DECIMALS }));
+        // the update at 1_600 came too soon for an observation, the one at 1_599 is used
+        assert_eq!(history.twap(1_900, 300), Ok(Price { value: 20_598_006_644, decimals: PRICE_HISTORY_DECIMALS }));
+    }
+
+    #[test]
+    fn twap_needs_history_covering_window() {
+        let mut history = PriceHistory::default();
+        assert_eq!(history.twap(1_000, 600), Err(TokenError::TwapUnavailable.into()));
+        history.record(&usd(10), 1_000).unwrap();
+        assert_eq!(history.twap(1_599, 600), Err(TokenError::TwapUnavailable.into()));
+        assert_eq!(history.twap(1_600, 600), Ok(scaled(10)));
+    }
+
+    #[test]
+    fn observations_are_spaced_and_wrap() {
+        let mut history = PriceHistory::default();
+        history.record(&usd(10), 0).unwrap();
+        history.record(&usd(10), PRICE_OBSERVATION_INTERVAL - 1).unwrap();
+        assert_eq!(history.observations.len(), 1);
+
+        for i in 1..=MAX_PRICE_OBSERVATIONS as i64 {
+            history.record(&usd(10), i * PRICE_OBSERVATION_INTERVAL).unwrap();
+        }
+        assert_eq!(history.observations.len(), MAX_PRICE_OBSERVATIONS);
+        assert_eq!(history.next_observation, 1);
+        // the observation at 0 was overwritten
+        assert!(history.observations.iter().all(|observation| observation.timestamp > 0));
+        assert_eq!(history.observations[0].timestamp, MAX_PRICE_OBSERVATIONS as i64 * PRICE_OBSERVATION_INTERVAL);
+    }
+
+    #[test]
+    fn clock_going_back_does_not_rewind() {
+        let mut history = PriceHistory::default();
+        history.record(&usd(10), 1_000).unwrap();
+        history.record(&usd(30), 990).unwrap();
+        assert_eq!(history.last_update, 1_000);
+        assert_eq!(history.twap(1_600, 600), Ok(scaled(30)));
+    }
+
+    #[test]
+    fn deviation_from_twap() {
+        let twap = scaled(100);
+        assert_eq!(check_deviation(&usd(105), &twap, 500), Ok(()));
+        assert_eq!(check_deviation(&usd(95), &twap, 500), Ok(()));
+        assert_eq!(check_deviation(&usd(106), &twap, 500), Err(TokenError::PriceDeviatesFromTwap.into()));
+        assert_eq!(
+            check_deviation(&Price { value: 9_499, decimals: 2 }, &twap, 500),
+            Err(TokenError::PriceDeviatesFromTwap.into())
+        );
+    }
+}
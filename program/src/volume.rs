@@ -0,0 +1,99 @@
+use crate::state::{FeeTier, UserStats, SECONDS_PER_DAY, VOLUME_DAYS};
+
+// Rolling swap volume of a client. Volume is kept per day in a ring of VOLUME_DAYS buckets,
+// a bucket is reused once its day falls out of the window, so the volume over the window
+// is the sum of the buckets of the days that are still in it.
+
+fn day_of(now: i64) -> i64 {
+    now.div_euclid(SECONDS_PER_DAY)
+}
+
+fn slot_of(day: i64) -> usize {
+    day.rem_euclid(VOLUME_DAYS as i64) as usize
+}
+
+impl UserStats {
+    // saturates rather than failing the swap that is being recorded
+    pub fn record_volume(&mut self, volume_usd: u64, now: i64) {
+        // cluster time may step back a little, it never rewinds the stats
+        let today = day_of(now).max(self.last_day);
+        // buckets of the days since the last swap still hold volume from VOLUME_DAYS ago
+        let stale_days = (today - self.last_day).min(VOLUME_DAYS as i64);
+        for day in today - stale_days + 1..=today {
+            self.daily_volume[slot_of(day)] = 0;
+        }
+        self.last_day = today;
+
+        let slot = slot_of(today);
+        self.daily_volume[slot] = self.daily_volume[slot].saturating_add(volume_usd);
+        self.total_volume = self.total_volume.saturating_add(volume_usd);
+    }
+
+    // USD volume of the last VOLUME_DAYS days, today included
+    pub fn rolling_volume(&self, now: i64) -> u64 {
+        let first_day = day_of(now).max(self.last_day) - VOLUME_DAYS as i64 + 1;
+        // days after the last swap had no volume
+        (first_day..=self.last_day).fold(0u64, |volume, day| volume.saturating_add(self.daily_volume[slot_of(day)]))
+    }
+}
+
+// fee of the highest tier the volume reaches, if it is lower than `fee_bps`
+pub fn tier_fee_bps(tiers: &[FeeTier], volume_usd: u64, fee_bps: u16) -> u16 {
+    match tiers.iter().rev().find(|tier| volume_usd >= tier.min_volume_usd) {
+        Some(tier) => tier.fee_bps.min(fee_bps),
+        None => fee_bps,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = SECONDS_PER_DAY;
+
+    #[test]
+    fn volume_rolls_over_window() {
+        let mut stats = UserStats::default();
+        let start = 19_000 * DAY;
+        stats.record_volume(100, start);
+        stats.record_volume(50, start + DAY / 2);
+        stats.record_volume(10, start + 10 * DAY);
+        assert_eq!(stats.rolling_volume(start + 10 * DAY), 160);
+        // the first day leaves the window after VOLUME_DAYS days
+        assert_eq!(stats.rolling_volume(start + (VOLUME_DAYS as i64 - 1) * DAY), 160);
+        assert_eq!(stats.rolling_volume(start + VOLUME_DAYS as i64 * DAY), 10);
+        assert_eq!(stats.rolling_volume(start + 100 * DAY), 0);
+        assert_eq!(stats.total_volume, 160);
+
+        // reusing the slot of the first day drops its old volume
+        stats.record_volume(1, start + VOLUME_DAYS as i64 * DAY);
+        assert_eq!(stats.rolling_volume(start + VOLUME_DAYS as i64 * DAY), 11);
+        stats.record_volume(7, start + 1_000 * DAY);
+        assert_eq!(stats.rolling_volume(start + 1_000 * DAY), 7);
+        assert_eq!(stats.total_volume, 168);
+    }
+
+    #[test]
+    fn clock_going_back_keeps_volume() {
+        let mut stats = UserStats::default();
+        stats.record_volume(100, 5 * DAY);
+        stats.record_volume(20, 4 * DAY);
+        assert_eq!(stats.last_day, 5);
+        assert_eq!(stats.rolling_volume(4 * DAY), 120);
+    }
+
+    #[test]
+    fn fee_tiers() {
+        let tiers = [
+            FeeTier { min_volume_usd: 10_000, fee_bps: 25 },
+            FeeTier { min_volume_usd: 1_000_000, fee_bps: 10 },
+        ];
+        assert_eq!(tier_fee_bps(&tiers, 0, 30), 30);
+        assert_eq!(tier_fee_bps(&tiers, 10_000, 30), 25);
+        assert_eq!(tier_fee_bps(&tiers, 999_999, 30), 25);
+        assert_eq!(tier_fee_bps(&tiers, 5_000_000, 30), 10);
+        // a pair cheaper than the tier keeps its own fee
+        assert_eq!(tier_fee_bps(&tiers, 5_000_000, 5), 5);
+        assert_eq!(tier_fee_bps(&[], 5_000_000, 30), 30);
+    }
+}
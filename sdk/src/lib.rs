@@ -0,0 +1,1235 @@
+// Instruction builders for the exchange program.
+//
+// Every builder returns a ready to sign `Instruction` with the account list in the
+// order the processor reads it. The exchange state account is derived here and
+// always goes first, except for `initialize_exchange` and `migrate`.
+
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+
+pub use bridge_contract_usdc_sol::error::TokenError;
+pub use bridge_contract_usdc_sol::instruction::{
+    self as exchange_instruction, DepositArgs, ExchangeArgs, Instruction as ExchangeInstruction,
+    MigrateArgs, PauseArgs, PauseScope, RegisterPairArgs, SetCircuitBreakerArgs, SetMaxPriceAgeArgs,
+    QuoteArgs, QuoteResult, SetFeeArgs, SetFeeTiersArgs, SetManualPriceArgs, SetMaxConfidenceArgs,
+    SetMaxManualPriceLifetimeArgs, SetOracleArgs, SetPairFeeArgs, SetPairPricingArgs, SetPairSpreadArgs,
+    SetPriceFeedsArgs, SetReferralShareArgs, SetReferrerArgs, SetRoleArgs, SetTokenEnabledArgs, SwapArgs,
+    SwapExactOutArgs, SwapMode, WithdrawArgs, WithdrawFeesArgs,
+};
+pub use bridge_contract_usdc_sol::state::{
+    self as exchange_state, AccountType, FeeTier, OracleProvider, PriceMode, Role, EXCHANGE_STATE_SEED, FEE_VAULT_SEED,
+    MANUAL_PRICE_SEED, PAIR_CONFIG_SEED, PRICE_HISTORY_SEED, TOKEN_CONFIG_SEED, USER_STATS_SEED,
+};
+
+pub fn find_exchange_state_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[EXCHANGE_STATE_SEED], program_id)
+}
+
+pub fn find_token_config_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TOKEN_CONFIG_SEED, mint.as_ref()], program_id)
+}
+
+pub fn find_pair_config_address(program_id: &Pubkey, base_mint: &Pubkey, quote_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[PAIR_CONFIG_SEED, base_mint.as_ref(), quote_mint.as_ref()],
+        program_id
+    )
+}
+
+pub fn find_manual_price_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MANUAL_PRICE_SEED, mint.as_ref()], program_id)
+}
+
+pub fn find_price_history_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PRICE_HISTORY_SEED, mint.as_ref()], program_id)
+}
+
+pub fn find_fee_vault_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[FEE_VAULT_SEED, mint.as_ref()], program_id)
+}
+
+pub fn find_user_stats_address(program_id: &Pubkey, wallet: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[USER_STATS_SEED, wallet.as_ref()], program_id)
+}
+
+// vault seeds use the exchange wallet recorded at InitializeExchange, not the current operator
+pub fn find_vault_address(program_id: &Pubkey, exchange_wallet: &Pubkey, pda_seed: &[u8; 16]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[exchange_wallet.as_ref(), pda_seed], program_id)
+}
+
+fn exchange_state_meta(program_id: &Pubkey, is_writable: bool) -> AccountMeta {
+    let (exchange_state, _) = find_exchange_state_address(program_id);
+    if is_writable {
+        AccountMeta::new(exchange_state, false)
+    } else {
+        AccountMeta::new_readonly(exchange_state, false)
+    }
+}
+
+fn build(program_id: &Pubkey, instruction: &ExchangeInstruction, accounts: Vec<AccountMeta>) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: instruction.pack(),
+    }
+}
+
+pub fn initialize_exchange(program_id: &Pubkey, exchange_wallet: &Pubkey) -> Instruction {
+    let (exchange_state, _) = find_exchange_state_address(program_id);
+    build(
+        program_id,
+        &ExchangeInstruction::InitializeExchange,
+        vec![
+            AccountMeta::new(*exchange_wallet, true),
+            AccountMeta::new(exchange_state, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn deposit(
+    program_id: &Pubkey,
+    exchange_wallet: &Pubkey,
+    operator: &Pubkey,
+    operator_token_account: &Pubkey,
+    mint: &Pubkey,
+    pda_seed: [u8; 16],
+    amount: u64,
+) -> Instruction {
+    let (vault, bump_seed) = find_vault_address(program_id, exchange_wallet, &pda_seed);
+    let (token_config, _) = find_token_config_address(program_id, mint);
+    build(
+        program_id,
+        &ExchangeInstruction::Deposit(DepositArgs { amount, pda_seed, bump_seed }),
+        vec![
+            exchange_state_meta(program_id, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(*operator, true),
+            AccountMeta::new(*operator_token_account, false),
+            AccountMeta::new(vault, false),
+            AccountMeta::new_readonly(*program_id, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(token_config, false),
+        ],
+    )
+}
+
+// operator signs, funds go to a token account of the exchange wallet
+pub fn withdraw(
+    program_id: &Pubkey,
+    operator: &Pubkey,
+    exchange_token_account: &Pubkey,
+    mint: &Pubkey,
+    vault: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        program_id,
+        &ExchangeInstruction::Withdraw(WithdrawArgs { amount }),
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(*operator, true),
+            AccountMeta::new(*exchange_token_account, false),
+            AccountMeta::new(*vault, false),
+        ],
+    )
+}
+
+// accounts of a swap, A is what the client pays, B is what the client gets
+#[derive(Clone, Debug)]
+pub struct SwapAccounts {
+    pub client_wallet: Pubkey,
+    pub client_token_a_account: Pubkey,
+    pub client_token_b_account: Pubkey,
+    pub exchange_wallet: Pubkey,
+    pub exchange_token_a_account: Pubkey,
+    pub exchange_token_b_account: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub data_feed_a: Pubkey,
+    pub data_feed_b: Pubkey,
+    pub chainlink_program: Pubkey,
+    // TokenConfig::extra_feeds of each mint, in the same order
+    pub extra_feeds_a: Vec<Pubkey>,
+    pub extra_feeds_b: Vec<Pubkey>,
+    // base mint of the registered pair, either mint_a or mint_b
+    pub pair_base_mint: Pubkey,
+    // token account of mint_b owned by an allowlisted referrer
+    pub referrer_token_account: Option<Pubkey>,
+    // passes the client's user stats, which have to be initialized, so the swap counts towards fee tiers
+    pub track_volume: bool,
+}
+
+impl SwapAccounts {
+    fn pair_quote_mint(&self) -> Pubkey {
+        if self.pair_base_mint == self.mint_a { self.mint_b } else { self.mint_a }
+    }
+
+    // the program id stands in for missing stats
+    fn client_stats_meta(&self, program_id: &Pubkey, is_writable: bool) -> AccountMeta {
+        if !self.track_volume {
+            return AccountMeta::new_readonly(*program_id, false);
+        }
+        let (client_stats, _) = find_user_stats_address(program_id, &self.client_wallet);
+        if is_writable {
+            AccountMeta::new(client_stats, false)
+        } else {
+            AccountMeta::new_readonly(client_stats, false)
+        }
+    }
+
+    fn to_account_metas(&self, program_id: &Pubkey) -> Vec<AccountMeta> {
+        let (token_config_a, _) = find_token_config_address(program_id, &self.mint_a);
+        let (token_config_b, _) = find_token_config_address(program_id, &self.mint_b);
+        let (pair_config, _) = find_pair_config_address(
+            program_id,
+            &self.pair_base_mint,
+            &self.pair_quote_mint()
+        );
+        let (price_history_a, _) = find_price_history_address(program_id, &self.mint_a);
+        let (price_history_b, _) = find_price_history_address(program_id, &self.mint_b);
+        let (fee_vault_b, _) = find_fee_vault_address(program_id, &self.mint_b);
+        let mut metas = vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(*program_id, false),
+            AccountMeta::new_readonly(self.mint_a, false),
+            AccountMeta::new_readonly(self.mint_b, false),
+            AccountMeta::new(self.client_wallet, true),
+            AccountMeta::new(self.client_token_a_account, false),
+            AccountMeta::new(self.client_token_b_account, false),
+            AccountMeta::new(self.exchange_wallet, true),
+            AccountMeta::new(self.exchange_token_a_account, false),
+            AccountMeta::new(self.exchange_token_b_account, false),
+            AccountMeta::new_readonly(self.data_feed_a, false),
+            AccountMeta::new_readonly(self.data_feed_b, false),
+            AccountMeta::new_readonly(self.chainlink_program, false),
+            AccountMeta::new_readonly(token_config_a, false),
+            AccountMeta::new(token_config_b, false),
+            AccountMeta::new(pair_config, false),
+            AccountMeta::new(price_history_a, false),
+            AccountMeta::new(price_history_b, false),
+            AccountMeta::new(fee_vault_b, false),
+            // the program id stands in for a missing referrer
+            match self.referrer_token_account {
+                Some(referrer_token_account) => AccountMeta::new(referrer_token_account, false),
+                None => AccountMeta::new_readonly(*program_id, false),
+            },
+            self.client_stats_meta(program_id, true),
+        ];
+        metas.extend(self.extra_feed_metas());
+        metas
+    }
+
+    // pricing accounts of the swap, all read-only
+    fn to_quote_account_metas(&self, program_id: &Pubkey) -> Vec<AccountMeta> {
+        let (token_config_a, _) = find_token_config_address(program_id, &self.mint_a);
+        let (token_config_b, _) = find_token_config_address(program_id, &self.mint_b);
+        let (pair_config, _) = find_pair_config_address(
+            program_id,
+            &self.pair_base_mint,
+            &self.pair_quote_mint()
+        );
+        let (price_history_a, _) = find_price_history_address(program_id, &self.mint_a);
+        let (price_history_b, _) = find_price_history_address(program_id, &self.mint_b);
+        let mut metas = vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new_readonly(self.mint_a, false),
+            AccountMeta::new_readonly(self.mint_b, false),
+            AccountMeta::new_readonly(self.data_feed_a, false),
+            AccountMeta::new_readonly(self.data_feed_b, false),
+            AccountMeta::new_readonly(self.chainlink_program, false),
+            AccountMeta::new_readonly(token_config_a, false),
+            AccountMeta::new_readonly(token_config_b, false),
+            AccountMeta::new_readonly(pair_config, false),
+            AccountMeta::new_readonly(price_history_a, false),
+            AccountMeta::new_readonly(price_history_b, false),
+            self.client_stats_meta(program_id, false),
+        ];
+        metas.extend(self.extra_feed_metas());
+        metas
+    }
+
+    fn extra_feed_metas(&self) -> impl Iterator<Item = AccountMeta> + '_ {
+        self.extra_feeds_a
+            .iter()
+            .chain(&self.extra_feeds_b)
+            .map(|price_feed| AccountMeta::new_readonly(*price_feed, false))
+    }
+}
+
+// works for any registered pair, wrapped SOL included
+pub fn swap(program_id: &Pubkey, accounts: &SwapAccounts, amount_in: u64, min_amount_out: u64) -> Instruction {
+    build(
+        program_id,
+        &ExchangeInstruction::Swap(SwapArgs { amount_in, min_amount_out }),
+        accounts.to_account_metas(program_id),
+    )
+}
+
+// client gets exactly `amount_out` of B and pays whatever it costs, up to `max_amount_in` of A
+pub fn swap_exact_out(
+    program_id: &Pubkey,
+    accounts: &SwapAccounts,
+    amount_out: u64,
+    max_amount_in: u64,
+) -> Instruction {
+    build(
+        program_id,
+        &ExchangeInstruction::SwapExactOut(SwapExactOutArgs { amount_out, max_amount_in }),
+        accounts.to_account_metas(program_id),
+    )
+}
+
+// read-only, meant for simulation: only the mints, feeds and pricing accounts of `accounts`
+// are used and the result comes back as borsh encoded `QuoteResult` return data
+pub fn quote(program_id: &Pubkey, accounts: &SwapAccounts, mode: SwapMode) -> Instruction {
+    build(
+        program_id,
+        &ExchangeInstruction::Quote(QuoteArgs { mode }),
+        accounts.to_quote_account_metas(program_id),
+    )
+}
+
+// legacy variants, same as `swap` but failing on a tripped circuit breaker
+pub fn swap_sol_to_token(
+    program_id: &Pubkey,
+    accounts: &SwapAccounts,
+    amount: u64,
+    min_amount_out: u64,
+) -> Instruction {
+    build(
+        program_id,
+        &ExchangeInstruction::ExchangeSolToToken(ExchangeArgs { amount, min_amount_out }),
+        accounts.to_account_metas(program_id),
+    )
+}
+
+pub fn swap_token_to_sol(
+    program_id: &Pubkey,
+    accounts: &SwapAccounts,
+    amount: u64,
+    min_amount_out: u64,
+) -> Instruction {
+    build(
+        program_id,
+        &ExchangeInstruction::ExchangeTokenToSol(ExchangeArgs { amount, min_amount_out }),
+        accounts.to_account_metas(program_id),
+    )
+}
+
+pub fn swap_token_to_token(
+    program_id: &Pubkey,
+    accounts: &SwapAccounts,
+    amount: u64,
+    min_amount_out: u64,
+) -> Instruction {
+    build(
+        program_id,
+        &ExchangeInstruction::ExchangeTokenToToken(ExchangeArgs { amount, min_amount_out }),
+        accounts.to_account_metas(program_id),
+    )
+}
+
+// also creates the fee vault of the mint
+pub fn register_token(program_id: &Pubkey, admin: &Pubkey, mint: &Pubkey, price_feed: &Pubkey) -> Instruction {
+    let (token_config, _) = find_token_config_address(program_id, mint);
+    let (fee_vault, _) = find_fee_vault_address(program_id, mint);
+    build(
+        program_id,
+        &ExchangeInstruction::RegisterToken,
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(token_config, false),
+            AccountMeta::new_readonly(*price_feed, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(fee_vault, false),
+        ],
+    )
+}
+
+pub fn set_token_enabled(program_id: &Pubkey, admin: &Pubkey, mint: &Pubkey, enabled: bool) -> Instruction {
+    let (token_config, _) = find_token_config_address(program_id, mint);
+    build(
+        program_id,
+        &ExchangeInstruction::SetTokenEnabled(SetTokenEnabledArgs { enabled }),
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(token_config, false),
+        ],
+    )
+}
+
+pub fn set_max_price_age(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    mint: &Pubkey,
+    max_price_age: u32,
+) -> Instruction {
+    let (token_config, _) = find_token_config_address(program_id, mint);
+    build(
+        program_id,
+        &ExchangeInstruction::SetMaxPriceAge(SetMaxPriceAgeArgs { max_price_age }),
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(token_config, false),
+        ],
+    )
+}
+
+pub fn set_max_confidence(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    mint: &Pubkey,
+    max_confidence_bps: u16,
+) -> Instruction {
+    let (token_config, _) = find_token_config_address(program_id, mint);
+    build(
+        program_id,
+        &ExchangeInstruction::SetMaxConfidence(SetMaxConfidenceArgs { max_confidence_bps }),
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(token_config, false),
+        ],
+    )
+}
+
+// caps how far ahead `set_manual_price` may put the expiry of the mint's manual price
+pub fn set_max_manual_price_lifetime(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    mint: &Pubkey,
+    max_manual_price_lifetime: u32,
+) -> Instruction {
+    let (token_config, _) = find_token_config_address(program_id, mint);
+    build(
+        program_id,
+        &ExchangeInstruction::SetMaxManualPriceLifetime(SetMaxManualPriceLifetimeArgs { max_manual_price_lifetime }),
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(token_config, false),
+        ],
+    )
+}
+
+pub fn set_oracle(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    mint: &Pubkey,
+    provider: OracleProvider,
+    price_feed: &Pubkey,
+) -> Instruction {
+    let (token_config, _) = find_token_config_address(program_id, mint);
+    build(
+        program_id,
+        &ExchangeInstruction::SetOracle(SetOracleArgs { provider }),
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(token_config, false),
+            AccountMeta::new_readonly(*price_feed, false),
+        ],
+    )
+}
+
+// the first feed becomes the primary one, a swap needs `min_valid_feeds` of them to be valid
+pub fn set_price_feeds(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    mint: &Pubkey,
+    feeds: &[(OracleProvider, Pubkey)],
+    min_valid_feeds: u8,
+) -> Instruction {
+    let (token_config, _) = find_token_config_address(program_id, mint);
+    let mut accounts = vec![
+        exchange_state_meta(program_id, false),
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new(token_config, false),
+    ];
+    accounts.extend(feeds.iter().map(|(_, price_feed)| AccountMeta::new_readonly(*price_feed, false)));
+    build(
+        program_id,
+        &ExchangeInstruction::SetPriceFeeds(SetPriceFeedsArgs {
+            providers: feeds.iter().map(|(provider, _)| *provider).collect(),
+            min_valid_feeds,
+        }),
+        accounts,
+    )
+}
+
+// select it with `set_oracle(.., OracleProvider::Manual, &find_manual_price_address(..).0)`
+pub fn set_manual_price(
+    program_id: &Pubkey,
+    operator: &Pubkey,
+    mint: &Pubkey,
+    price: u64,
+    exponent: i32,
+    expires_at: i64,
+) -> Instruction {
+    let (token_config, _) = find_token_config_address(program_id, mint);
+    let (manual_price, _) = find_manual_price_address(program_id, mint);
+    build(
+        program_id,
+        &ExchangeInstruction::SetManualPrice(SetManualPriceArgs { price, exponent, expires_at }),
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new(*operator, true),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(token_config, false),
+            AccountMeta::new(manual_price, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn set_role(program_id: &Pubkey, admin: &Pubkey, role: Role, authority: &Pubkey) -> Instruction {
+    build(
+        program_id,
+        &ExchangeInstruction::SetRole(SetRoleArgs { role, authority: *authority }),
+        vec![
+            exchange_state_meta(program_id, true),
+            AccountMeta::new_readonly(*admin, true),
+        ],
+    )
+}
+
+pub fn register_pair(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+    max_price_change_bps: u16,
+) -> Instruction {
+    let (base_config, _) = find_token_config_address(program_id, base_mint);
+    let (quote_config, _) = find_token_config_address(program_id, quote_mint);
+    let (pair_config, _) = find_pair_config_address(program_id, base_mint, quote_mint);
+    build(
+        program_id,
+        &ExchangeInstruction::RegisterPair(RegisterPairArgs { max_price_change_bps }),
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(*base_mint, false),
+            AccountMeta::new_readonly(*quote_mint, false),
+            AccountMeta::new_readonly(base_config, false),
+            AccountMeta::new_readonly(quote_config, false),
+            AccountMeta::new(pair_config, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn set_circuit_breaker(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+    max_price_change_bps: u16,
+) -> Instruction {
+    let (pair_config, _) = find_pair_config_address(program_id, base_mint, quote_mint);
+    build(
+        program_id,
+        &ExchangeInstruction::SetCircuitBreaker(SetCircuitBreakerArgs { max_price_change_bps }),
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(pair_config, false),
+        ],
+    )
+}
+
+pub fn set_pair_pricing(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+    price_mode: PriceMode,
+    twap_window: u32,
+    max_twap_deviation_bps: u16,
+) -> Instruction {
+    let (pair_config, _) = find_pair_config_address(program_id, base_mint, quote_mint);
+    build(
+        program_id,
+        &ExchangeInstruction::SetPairPricing(SetPairPricingArgs {
+            price_mode,
+            twap_window,
+            max_twap_deviation_bps,
+        }),
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(pair_config, false),
+        ],
+    )
+}
+
+// permissionless, creates the mint's price history on first use; `extra_feeds` as in `SwapAccounts`
+pub fn update_price(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    mint: &Pubkey,
+    data_feed: &Pubkey,
+    chainlink_program: &Pubkey,
+    extra_feeds: &[Pubkey],
+) -> Instruction {
+    let (token_config, _) = find_token_config_address(program_id, mint);
+    let (price_history, _) = find_price_history_address(program_id, mint);
+    let mut accounts = vec![
+        exchange_state_meta(program_id, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new_readonly(token_config, false),
+        AccountMeta::new(price_history, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(*data_feed, false),
+        AccountMeta::new_readonly(*chainlink_program, false),
+    ];
+    accounts.extend(extra_feeds.iter().map(|price_feed| AccountMeta::new_readonly(*price_feed, false)));
+    build(program_id, &ExchangeInstruction::UpdatePrice, accounts)
+}
+
+pub fn set_fee(program_id: &Pubkey, admin: &Pubkey, fee_bps: u16) -> Instruction {
+    build(
+        program_id,
+        &ExchangeInstruction::SetFee(SetFeeArgs { fee_bps }),
+        vec![
+            exchange_state_meta(program_id, true),
+            AccountMeta::new_readonly(*admin, true),
+        ],
+    )
+}
+
+// `None` makes the pair use the exchange fee again
+pub fn set_pair_fee(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+    fee_bps: Option<u16>,
+) -> Instruction {
+    let (pair_config, _) = find_pair_config_address(program_id, base_mint, quote_mint);
+    build(
+        program_id,
+        &ExchangeInstruction::SetPairFee(SetPairFeeArgs { fee_bps }),
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(pair_config, false),
+        ],
+    )
+}
+
+pub fn set_pair_spread(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+    bid_spread_bps: u16,
+    ask_spread_bps: u16,
+) -> Instruction {
+    let (pair_config, _) = find_pair_config_address(program_id, base_mint, quote_mint);
+    build(
+        program_id,
+        &ExchangeInstruction::SetPairSpread(SetPairSpreadArgs { bid_spread_bps, ask_spread_bps }),
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(pair_config, false),
+        ],
+    )
+}
+
+pub fn set_referral_share(program_id: &Pubkey, admin: &Pubkey, referral_share_bps: u16) -> Instruction {
+    build(
+        program_id,
+        &ExchangeInstruction::SetReferralShare(SetReferralShareArgs { referral_share_bps }),
+        vec![
+            exchange_state_meta(program_id, true),
+            AccountMeta::new_readonly(*admin, true),
+        ],
+    )
+}
+
+pub fn set_referrer(program_id: &Pubkey, admin: &Pubkey, referrer: &Pubkey, allowed: bool) -> Instruction {
+    build(
+        program_id,
+        &ExchangeInstruction::SetReferrer(SetReferrerArgs { referrer: *referrer, allowed }),
+        vec![
+            exchange_state_meta(program_id, true),
+            AccountMeta::new_readonly(*admin, true),
+        ],
+    )
+}
+
+// tiers must be sorted by ascending `min_volume_usd`, an empty list disables them
+pub fn set_fee_tiers(program_id: &Pubkey, admin: &Pubkey, tiers: Vec<FeeTier>) -> Instruction {
+    build(
+        program_id,
+        &ExchangeInstruction::SetFeeTiers(SetFeeTiersArgs { tiers }),
+        vec![
+            exchange_state_meta(program_id, true),
+            AccountMeta::new_readonly(*admin, true),
+        ],
+    )
+}
+
+// permissionless, swaps of `wallet` count towards its fee tier once its stats exist
+pub fn initialize_user_stats(program_id: &Pubkey, payer: &Pubkey, wallet: &Pubkey) -> Instruction {
+    let (user_stats, _) = find_user_stats_address(program_id, wallet);
+    build(
+        program_id,
+        &ExchangeInstruction::InitializeUserStats,
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(*wallet, false),
+            AccountMeta::new(user_stats, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+// permissionless, gives a fee vault to a mint registered before fees existed
+pub fn initialize_fee_vault(program_id: &Pubkey, payer: &Pubkey, mint: &Pubkey) -> Instruction {
+    let (token_config, _) = find_token_config_address(program_id, mint);
+    let (fee_vault, _) = find_fee_vault_address(program_id, mint);
+    build(
+        program_id,
+        &ExchangeInstruction::InitializeFeeVault,
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(token_config, false),
+            AccountMeta::new(fee_vault, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn withdraw_fees(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    mint: &Pubkey,
+    destination_token_account: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (token_config, _) = find_token_config_address(program_id, mint);
+    let (fee_vault, _) = find_fee_vault_address(program_id, mint);
+    build(
+        program_id,
+        &ExchangeInstruction::WithdrawFees(WithdrawFeesArgs { amount }),
+        vec![
+            exchange_state_meta(program_id, false),
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(token_config, false),
+            AccountMeta::new(fee_vault, false),
+            AccountMeta::new(*destination_token_account, false),
+        ],
+    )
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum PauseTarget {
+    Global,
+    Mint(Pubkey),
+    Pair { base_mint: Pubkey, quote_mint: Pubkey },
+}
+
+fn pause_accounts(program_id: &Pubkey, authority: &Pubkey, target: &PauseTarget) -> (PauseScope, Vec<AccountMeta>) {
+    match target {
+        PauseTarget::Global => (
+            PauseScope::Global,
+            vec![
+                exchange_state_meta(program_id, true),
+                AccountMeta::new_readonly(*authority, true),
+            ],
+        ),
+        PauseTarget::Mint(mint) => {
+            let (token_config, _) = find_token_config_address(program_id, mint);
+            (
+                PauseScope::Mint,
+                vec![
+                    exchange_state_meta(program_id, false),
+                    AccountMeta::new_readonly(*authority, true),
+                    AccountMeta::new_readonly(*mint, false),
+                    AccountMeta::new(token_config, false),
+                ],
+            )
+        }
+        PauseTarget::Pair { base_mint, quote_mint } => {
+            let (pair_config, _) = find_pair_config_address(program_id, base_mint, quote_mint);
+            (
+                PauseScope::Pair,
+                vec![
+                    exchange_state_meta(program_id, false),
+                    AccountMeta::new_readonly(*authority, true),
+                    AccountMeta::new(pair_config, false),
+                ],
+            )
+        }
+    }
+}
+
+pub fn pause(program_id: &Pubkey, pauser: &Pubkey, target: PauseTarget) -> Instruction {
+    let (scope, accounts) = pause_accounts(program_id, pauser, &target);
+    build(program_id, &ExchangeInstruction::Pause(PauseArgs { scope }), accounts)
+}
+
+pub fn unpause(program_id: &Pubkey, admin: &Pubkey, target: PauseTarget) -> Instruction {
+    let (scope, accounts) = pause_accounts(program_id, admin, &target);
+    build(program_id, &ExchangeInstruction::Unpause(PauseArgs { scope }), accounts)
+}
+
+pub fn migrate(program_id: &Pubkey, payer: &Pubkey, account: &Pubkey, account_type: AccountType) -> Instruction {
+    build(
+        program_id,
+        &ExchangeInstruction::Migrate(MigrateArgs { account_type }),
+        vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(*account, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(instruction: &Instruction) -> Vec<(Pubkey, bool, bool)> {
+        instruction.accounts.iter().map(|meta| (meta.pubkey, meta.is_signer, meta.is_writable)).collect()
+    }
+
+    fn swap_accounts() -> SwapAccounts {
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        SwapAccounts {
+            client_wallet: Pubkey::new_unique(),
+            client_token_a_account: Pubkey::new_unique(),
+            client_token_b_account: Pubkey::new_unique(),
+            exchange_wallet: Pubkey::new_unique(),
+            exchange_token_a_account: Pubkey::new_unique(),
+            exchange_token_b_account: Pubkey::new_unique(),
+            mint_a,
+            mint_b,
+            data_feed_a: Pubkey::new_unique(),
+            data_feed_b: Pubkey::new_unique(),
+            chainlink_program: Pubkey::new_unique(),
+            extra_feeds_a: vec![Pubkey::new_unique()],
+            extra_feeds_b: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            pair_base_mint: mint_b,
+            referrer_token_account: None,
+            track_volume: false,
+        }
+    }
+
+    #[test]
+    fn initialize_exchange_and_migrate_accounts() {
+        let program_id = Pubkey::new_unique();
+        let (exchange_state, _) = find_exchange_state_address(&program_id);
+        let exchange_wallet = Pubkey::new_unique();
+        assert_eq!(
+            keys(&initialize_exchange(&program_id, &exchange_wallet)),
+            [(exchange_wallet, true, true), (exchange_state, false, true), (system_program::id(), false, false)]
+        );
+
+        let (payer, account) = (Pubkey::new_unique(), Pubkey::new_unique());
+        assert_eq!(
+            keys(&migrate(&program_id, &payer, &account, AccountType::PairConfig)),
+            [(payer, true, true), (account, false, true), (system_program::id(), false, false)]
+        );
+    }
+
+    #[test]
+    fn deposit_and_withdraw_accounts() {
+        let program_id = Pubkey::new_unique();
+        let (exchange_state, _) = find_exchange_state_address(&program_id);
+        let (exchange_wallet, operator, operator_token_account, mint) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let pda_seed = [3; 16];
+        let (vault, bump_seed) = find_vault_address(&program_id, &exchange_wallet, &pda_seed);
+
+        let instruction = deposit(&program_id, &exchange_wallet, &operator, &operator_token_account, &mint, pda_seed, 5);
+        assert_eq!(
+            ExchangeInstruction::unpack(&instruction.data),
+            Ok(ExchangeInstruction::Deposit(DepositArgs { amount: 5, pda_seed, bump_seed }))
+        );
+        assert_eq!(
+            keys(&instruction),
+            [
+                (exchange_state, false, true),
+                (spl_token::id(), false, false),
+                (mint, false, false),
+                (operator, true, true),
+                (operator_token_account, false, true),
+                (vault, false, true),
+                (program_id, false, false),
+                (system_program::id(), false, false),
+                (find_token_config_address(&program_id, &mint).0, false, false),
+            ]
+        );
+
+        let exchange_token_account = Pubkey::new_unique();
+        assert_eq!(
+            keys(&withdraw(&program_id, &operator, &exchange_token_account, &mint, &vault, 5)),
+            [
+                (exchange_state, false, false),
+                (spl_token::id(), false, false),
+                (mint, false, false),
+                (operator, true, false),
+                (exchange_token_account, false, true),
+                (vault, false, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn swap_accounts_order() {
+        let program_id = Pubkey::new_unique();
+        let a = swap_accounts();
+        let instructions = [
+            swap(&program_id, &a, 10, 9),
+            swap_exact_out(&program_id, &a, 9, 10),
+            swap_sol_to_token(&program_id, &a, 10, 9),
+            swap_token_to_sol(&program_id, &a, 10, 9),
+            swap_token_to_token(&program_id, &a, 10, 9),
+        ];
+        for instruction in &instructions[1..] {
+            assert_eq!(instruction.accounts, instructions[0].accounts);
+        }
+        assert_eq!(
+            keys(&instructions[0]),
+            [
+                (find_exchange_state_address(&program_id).0, false, false),
+                (spl_token::id(), false, false),
+                (program_id, false, false),
+                (a.mint_a, false, false),
+                (a.mint_b, false, false),
+                (a.client_wallet, true, true),
+                (a.client_token_a_account, false, true),
+                (a.client_token_b_account, false, true),
+                (a.exchange_wallet, true, true),
+                (a.exchange_token_a_account, false, true),
+                (a.exchange_token_b_account, false, true),
+                (a.data_feed_a, false, false),
+                (a.data_feed_b, false, false),
+                (a.chainlink_program, false, false),
+                (find_token_config_address(&program_id, &a.mint_a).0, false, false),
+                (find_token_config_address(&program_id, &a.mint_b).0, false, true),
+                // pair is registered with mint_b as base
+                (find_pair_config_address(&program_id, &a.mint_b, &a.mint_a).0, false, true),
+                (find_price_history_address(&program_id, &a.mint_a).0, false, true),
+                (find_price_history_address(&program_id, &a.mint_b).0, false, true),
+                (find_fee_vault_address(&program_id, &a.mint_b).0, false, true),
+                // no referrer and no client stats
+                (program_id, false, false),
+                (program_id, false, false),
+                (a.extra_feeds_a[0], false, false),
+                (a.extra_feeds_b[0], false, false),
+                (a.extra_feeds_b[1], false, false),
+            ]
+        );
+
+        let referrer_token_account = Pubkey::new_unique();
+        let a = SwapAccounts { referrer_token_account: Some(referrer_token_account), ..a };
+        assert_eq!(keys(&swap(&program_id, &a, 10, 9))[20], (referrer_token_account, false, true));
+        let a = SwapAccounts { track_volume: true, ..a };
+        let (client_stats, _) = find_user_stats_address(&program_id, &a.client_wallet);
+        assert_eq!(keys(&swap(&program_id, &a, 10, 9))[21], (client_stats, false, true));
+    }
+
+    #[test]
+    fn fee_accounts() {
+        let program_id = Pubkey::new_unique();
+        let (exchange_state, _) = find_exchange_state_address(&program_id);
+        let (admin, mint, quote_mint, destination) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (token_config, _) = find_token_config_address(&program_id, &mint);
+        let (fee_vault, _) = find_fee_vault_address(&program_id, &mint);
+
+        for instruction in [
+            set_fee(&program_id, &admin, 30),
+            set_referral_share(&program_id, &admin, 2_000),
+            set_referrer(&program_id, &admin, &destination, true),
+            set_fee_tiers(&program_id, &admin, vec![FeeTier { min_volume_usd: 1_000, fee_bps: 20 }]),
+        ] {
+            assert_eq!(keys(&instruction), [(exchange_state, false, true), (admin, true, false)]);
+        }
+        assert_eq!(
+            keys(&set_pair_fee(&program_id, &admin, &mint, &quote_mint, None)),
+            [
+                (exchange_state, false, false),
+                (admin, true, false),
+                (find_pair_config_address(&program_id, &mint, &quote_mint).0, false, true),
+            ]
+        );
+        assert_eq!(
+            keys(&initialize_fee_vault(&program_id, &admin, &mint)),
+            [
+                (exchange_state, false, false),
+                (admin, true, true),
+                (spl_token::id(), false, false),
+                (mint, false, false),
+                (token_config, false, true),
+                (fee_vault, false, true),
+                (system_program::id(), false, false),
+            ]
+        );
+        assert_eq!(
+            keys(&withdraw_fees(&program_id, &admin, &mint, &destination, 5)),
+            [
+                (exchange_state, false, false),
+                (admin, true, false),
+                (spl_token::id(), false, false),
+                (mint, false, false),
+                (token_config, false, true),
+                (fee_vault, false, true),
+                (destination, false, true),
+            ]
+        );
+
+        assert_eq!(
+            keys(&initialize_user_stats(&program_id, &admin, &destination)),
+            [
+                (exchange_state, false, false),
+                (admin, true, true),
+                (destination, false, false),
+                (find_user_stats_address(&program_id, &destination).0, false, true),
+                (system_program::id(), false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn quote_accounts_are_read_only() {
+        let program_id = Pubkey::new_unique();
+        let a = swap_accounts();
+        let instruction = quote(&program_id, &a, SwapMode::ExactIn { amount_in: 10, min_amount_out: 0 });
+        assert_eq!(
+            keys(&instruction),
+            [
+                (find_exchange_state_address(&program_id).0, false, false),
+                (a.mint_a, false, false),
+                (a.mint_b, false, false),
+                (a.data_feed_a, false, false),
+                (a.data_feed_b, false, false),
+                (a.chainlink_program, false, false),
+                (find_token_config_address(&program_id, &a.mint_a).0, false, false),
+                (find_token_config_address(&program_id, &a.mint_b).0, false, false),
+                (find_pair_config_address(&program_id, &a.mint_b, &a.mint_a).0, false, false),
+                (find_price_history_address(&program_id, &a.mint_a).0, false, false),
+                (find_price_history_address(&program_id, &a.mint_b).0, false, false),
+                // no client stats
+                (program_id, false, false),
+                (a.extra_feeds_a[0], false, false),
+                (a.extra_feeds_b[0], false, false),
+                (a.extra_feeds_b[1], false, false),
+            ]
+        );
+
+        // stats only pick the fee tier of a quote
+        let a = SwapAccounts { track_volume: true, ..a };
+        let instruction = quote(&program_id, &a, SwapMode::ExactIn { amount_in: 10, min_amount_out: 0 });
+        let (client_stats, _) = find_user_stats_address(&program_id, &a.client_wallet);
+        assert_eq!(keys(&instruction)[11], (client_stats, false, false));
+    }
+
+    #[test]
+    fn token_and_pair_config_accounts() {
+        let program_id = Pubkey::new_unique();
+        let (exchange_state, _) = find_exchange_state_address(&program_id);
+        let (admin, mint, price_feed, quote_mint) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (token_config, _) = find_token_config_address(&program_id, &mint);
+        let (quote_config, _) = find_token_config_address(&program_id, &quote_mint);
+        let (pair_config, _) = find_pair_config_address(&program_id, &mint, &quote_mint);
+
+        assert_eq!(
+            keys(&register_token(&program_id, &admin, &mint, &price_feed)),
+            [
+                (exchange_state, false, false),
+                (admin, true, true),
+                (mint, false, false),
+                (token_config, false, true),
+                (price_feed, false, false),
+                (system_program::id(), false, false),
+                (spl_token::id(), false, false),
+                (find_fee_vault_address(&program_id, &mint).0, false, true),
+            ]
+        );
+        for instruction in [
+            set_token_enabled(&program_id, &admin, &mint, false),
+            set_max_price_age(&program_id, &admin, &mint, 30),
+            set_max_confidence(&program_id, &admin, &mint, 50),
+            set_max_manual_price_lifetime(&program_id, &admin, &mint, 600),
+        ] {
+            assert_eq!(
+                keys(&instruction),
+                [(exchange_state, false, false), (admin, true, false), (mint, false, false), (token_config, false, true)]
+            );
+        }
+        assert_eq!(
+            keys(&set_oracle(&program_id, &admin, &mint, OracleProvider::Chainlink, &price_feed)),
+            [
+                (exchange_state, false, false),
+                (admin, true, false),
+                (mint, false, false),
+                (token_config, false, true),
+                (price_feed, false, false),
+            ]
+        );
+        let extra_feed = Pubkey::new_unique();
+        let instruction = set_price_feeds(
+            &program_id,
+            &admin,
+            &mint,
+            &[(OracleProvider::Pyth, price_feed), (OracleProvider::Switchboard, extra_feed)],
+            2
+        );
+        assert_eq!(
+            ExchangeInstruction::unpack(&instruction.data),
+            Ok(ExchangeInstruction::SetPriceFeeds(SetPriceFeedsArgs {
+                providers: vec![OracleProvider::Pyth, OracleProvider::Switchboard],
+                min_valid_feeds: 2,
+            }))
+        );
+        assert_eq!(
+            keys(&instruction),
+            [
+                (exchange_state, false, false),
+                (admin, true, false),
+                (mint, false, false),
+                (token_config, false, true),
+                (price_feed, false, false),
+                (extra_feed, false, false),
+            ]
+        );
+        assert_eq!(
+            keys(&register_pair(&program_id, &admin, &mint, &quote_mint, 500)),
+            [
+                (exchange_state, false, false),
+                (admin, true, true),
+                (mint, false, false),
+                (quote_mint, false, false),
+                (token_config, false, false),
+                (quote_config, false, false),
+                (pair_config, false, true),
+                (system_program::id(), false, false),
+            ]
+        );
+        for instruction in [
+            set_circuit_breaker(&program_id, &admin, &mint, &quote_mint, 500),
+            set_pair_pricing(&program_id, &admin, &mint, &quote_mint, PriceMode::Twap, 600, 50),
+            set_pair_spread(&program_id, &admin, &mint, &quote_mint, 25, 40),
+        ] {
+            assert_eq!(keys(&instruction), [(exchange_state, false, false), (admin, true, false), (pair_config, false, true)]);
+        }
+        assert_eq!(
+            keys(&set_role(&program_id, &admin, Role::Operator, &quote_mint)),
+            [(exchange_state, false, true), (admin, true, false)]
+        );
+    }
+
+    #[test]
+    fn manual_price_accounts() {
+        let program_id = Pubkey::new_unique();
+        let (operator, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let instruction = set_manual_price(&program_id, &operator, &mint, 1, -8, 0);
+        assert_eq!(
+            ExchangeInstruction::unpack(&instruction.data),
+            Ok(ExchangeInstruction::SetManualPrice(SetManualPriceArgs { price: 1, exponent: -8, expires_at: 0 }))
+        );
+        assert_eq!(
+            keys(&instruction),
+            [
+                (find_exchange_state_address(&program_id).0, false, false),
+                (operator, true, true),
+                (mint, false, false),
+                (find_token_config_address(&program_id, &mint).0, false, false),
+                (find_manual_price_address(&program_id, &mint).0, false, true),
+                (system_program::id(), false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn update_price_accounts() {
+        let program_id = Pubkey::new_unique();
+        let (payer, mint, data_feed, chainlink_program, extra_feed) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        assert_eq!(
+            keys(&update_price(&program_id, &payer, &mint, &data_feed, &chainlink_program, &[extra_feed])),
+            [
+                (find_exchange_state_address(&program_id).0, false, false),
+                (payer, true, true),
+                (mint, false, false),
+                (find_token_config_address(&program_id, &mint).0, false, false),
+                (find_price_history_address(&program_id, &mint).0, false, true),
+                (system_program::id(), false, false),
+                (data_feed, false, false),
+                (chainlink_program, false, false),
+                (extra_feed, false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn pause_accounts_by_scope() {
+        let program_id = Pubkey::new_unique();
+        let (exchange_state, _) = find_exchange_state_address(&program_id);
+        let (pauser, mint, base_mint, quote_mint) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        for (target, scope, expected) in [
+            (PauseTarget::Global, PauseScope::Global, vec![(exchange_state, false, true), (pauser, true, false)]),
+            (
+                PauseTarget::Mint(mint),
+                PauseScope::Mint,
+                vec![
+                    (exchange_state, false, false),
+                    (pauser, true, false),
+                    (mint, false, false),
+                    (find_token_config_address(&program_id, &mint).0, false, true),
+                ],
+            ),
+            (
+                PauseTarget::Pair { base_mint, quote_mint },
+                PauseScope::Pair,
+                vec![
+                    (exchange_state, false, false),
+                    (pauser, true, false),
+                    (find_pair_config_address(&program_id, &base_mint, &quote_mint).0, false, true),
+                ],
+            ),
+        ] {
+            let instruction = pause(&program_id, &pauser, target);
+            assert_eq!(
+                ExchangeInstruction::unpack(&instruction.data),
+                Ok(ExchangeInstruction::Pause(PauseArgs { scope }))
+            );
+            assert_eq!(keys(&instruction), expected);
+            assert_eq!(keys(&unpause(&program_id, &pauser, target)), expected);
+        }
+    }
+}
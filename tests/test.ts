@@ -120,14 +120,263 @@ describe("Test1", async () => {
         "HEvSKofvBgfaexv23kMabbYqxasxU3mQ4ibBMEmJWHny",
     );
 
+    const INSTRUCTION_VERSION = 1;
+    // vault PDA seed, must be exactly 16 bytes
+    const vaultSeed = Buffer.from("toKeNpDaSeEd0000");
+
+    const exchangeState = PublicKey.findProgramAddressSync(
+        [Buffer.from("exchange_state")],
+        exchangeProgram
+    )[0];
+
+    it("Initialize exchange", async() => {
+
+        const instructionData = borsh.struct([
+            borsh.u8('version'),
+            borsh.u8('variant'),
+        ])
+        const buffer = Buffer.alloc(1000);
+        instructionData.encode({version: INSTRUCTION_VERSION, variant: 5}, buffer);
+        const instructionBuffer = buffer.slice(0, instructionData.getSpan(buffer));
+
+        let ix = new TransactionInstruction({
+            keys: [
+                {pubkey: exchangeWallet.publicKey, isSigner: true, isWritable: true},
+                {pubkey: exchangeState, isSigner: false, isWritable: true},
+                {pubkey: system_program_id, isSigner: false, isWritable: false},
+            ],
+            data: instructionBuffer,
+            programId: exchangeProgram,
+        });
+
+        let tx = new Transaction();
+        tx.recentBlockhash = (await connection.getLatestBlockhash('finalized')).blockhash;
+        tx.feePayer = exchangeWallet.publicKey;
+        tx.add(ix);
+
+        let sim_result = await connection.simulateTransaction(tx);
+        console.log("logs : {}", sim_result.value.logs);
+    });
+
+    function findTokenConfig(mint: PublicKey): PublicKey {
+        return PublicKey.findProgramAddressSync(
+            [Buffer.from("token_config"), mint.toBuffer()],
+            exchangeProgram
+        )[0];
+    }
+
+    it("Register tokens", async() => {
+
+        const instructionData = borsh.struct([
+            borsh.u8('version'),
+            borsh.u8('variant'),
+        ])
+        const buffer = Buffer.alloc(1000);
+        instructionData.encode({version: INSTRUCTION_VERSION, variant: 6}, buffer);
+        const instructionBuffer = buffer.slice(0, instructionData.getSpan(buffer));
+
+        let tx = new Transaction();
+        for (const [mint, dataFeed] of [
+            [NATIVE_MINT, solToUsdDataFeedAccount],
+            [USDC_MINT, usdcToUsdDataFeedAccount],
+        ]) {
+            tx.add(new TransactionInstruction({
+                keys: [
+                    {pubkey: exchangeState, isSigner: false, isWritable: false},
+                    {pubkey: exchangeWallet.publicKey, isSigner: true, isWritable: true},
+                    {pubkey: mint, isSigner: false, isWritable: false},
+                    {pubkey: findTokenConfig(mint), isSigner: false, isWritable: true},
+                    {pubkey: dataFeed, isSigner: false, isWritable: false},
+                    {pubkey: system_program_id, isSigner: false, isWritable: false},
+                    {pubkey: token_program_id, isSigner: false, isWritable: false},
+                    {pubkey: findFeeVault(mint), isSigner: false, isWritable: true},
+                ],
+                data: instructionBuffer,
+                programId: exchangeProgram,
+            }));
+        }
+        tx.recentBlockhash = (await connection.getLatestBlockhash('finalized')).blockhash;
+        tx.feePayer = exchangeWallet.publicKey;
+
+        let sim_result = await connection.simulateTransaction(tx);
+        console.log("logs : {}", sim_result.value.logs);
+    });
+
+    function findPairConfig(baseMint: PublicKey, quoteMint: PublicKey): PublicKey {
+        return PublicKey.findProgramAddressSync(
+            [Buffer.from("pair_config"), baseMint.toBuffer(), quoteMint.toBuffer()],
+            exchangeProgram
+        )[0];
+    }
+
+    it("Register SOL/USDC pair", async() => {
+
+        const instructionData = borsh.struct([
+            borsh.u8('version'),
+            borsh.u8('variant'),
+            borsh.u16('max_price_change_bps'),
+        ])
+        const buffer = Buffer.alloc(1000);
+        instructionData.encode({version: INSTRUCTION_VERSION, variant: 9, max_price_change_bps: 1000}, buffer);
+        const instructionBuffer = buffer.slice(0, instructionData.getSpan(buffer));
+
+        let ix = new TransactionInstruction({
+            keys: [
+                {pubkey: exchangeState, isSigner: false, isWritable: false},
+                {pubkey: exchangeWallet.publicKey, isSigner: true, isWritable: true},
+                {pubkey: NATIVE_MINT, isSigner: false, isWritable: false},
+                {pubkey: USDC_MINT, isSigner: false, isWritable: false},
+                {pubkey: findTokenConfig(NATIVE_MINT), isSigner: false, isWritable: false},
+                {pubkey: findTokenConfig(USDC_MINT), isSigner: false, isWritable: false},
+                {pubkey: findPairConfig(NATIVE_MINT, USDC_MINT), isSigner: false, isWritable: true},
+                {pubkey: system_program_id, isSigner: false, isWritable: false},
+            ],
+            data: instructionBuffer,
+            programId: exchangeProgram,
+        });
+
+        let tx = new Transaction();
+        tx.recentBlockhash = (await connection.getLatestBlockhash('finalized')).blockhash;
+        tx.feePayer = exchangeWallet.publicKey;
+        tx.add(ix);
+
+        let sim_result = await connection.simulateTransaction(tx);
+        console.log("logs : {}", sim_result.value.logs);
+    });
+
+    function findPriceHistory(mint: PublicKey): PublicKey {
+        return PublicKey.findProgramAddressSync(
+            [Buffer.from("price_history"), mint.toBuffer()],
+            exchangeProgram
+        )[0];
+    }
+
+    // swaps need the price history of both tokens, UpdatePrice creates it
+    it("Update prices", async() => {
+
+        const instructionData = borsh.struct([
+            borsh.u8('version'),
+            borsh.u8('variant'),
+        ])
+        const buffer = Buffer.alloc(1000);
+        instructionData.encode({version: INSTRUCTION_VERSION, variant: 20}, buffer);
+        const instructionBuffer = buffer.slice(0, instructionData.getSpan(buffer));
+
+        let tx = new Transaction();
+        for (const [mint, dataFeed] of [
+            [NATIVE_MINT, solToUsdDataFeedAccount],
+            [USDC_MINT, usdcToUsdDataFeedAccount],
+        ]) {
+            tx.add(new TransactionInstruction({
+                keys: [
+                    {pubkey: exchangeState, isSigner: false, isWritable: false},
+                    {pubkey: payer.publicKey, isSigner: true, isWritable: true},
+                    {pubkey: mint, isSigner: false, isWritable: false},
+                    {pubkey: findTokenConfig(mint), isSigner: false, isWritable: false},
+                    {pubkey: findPriceHistory(mint), isSigner: false, isWritable: true},
+                    {pubkey: system_program_id, isSigner: false, isWritable: false},
+                    {pubkey: dataFeed, isSigner: false, isWritable: false},
+                    {pubkey: chainLinkProgramId, isSigner: false, isWritable: false},
+                ],
+                data: instructionBuffer,
+                programId: exchangeProgram,
+            }));
+        }
+        tx.recentBlockhash = (await connection.getLatestBlockhash('finalized')).blockhash;
+        tx.feePayer = payer.publicKey;
+
+        let sim_result = await connection.simulateTransaction(tx);
+        console.log("logs : {}", sim_result.value.logs);
+    });
+
+    function findFeeVault(mint: PublicKey): PublicKey {
+        return PublicKey.findProgramAddressSync(
+            [Buffer.from("fee_vault"), mint.toBuffer()],
+            exchangeProgram
+        )[0];
+    }
+
+    // token accounts of an allowlisted wallet can be passed as referrer of a swap
+    it("Allow referrer", async() => {
+
+        const instructionData = borsh.struct([
+            borsh.u8('version'),
+            borsh.u8('variant'),
+            borsh.publicKey('referrer'),
+            borsh.bool('allowed'),
+        ])
+        const buffer = Buffer.alloc(1000);
+        instructionData.encode({
+            version: INSTRUCTION_VERSION,
+            variant: 31,
+            referrer: payer.publicKey,
+            allowed: true,
+        }, buffer);
+        const instructionBuffer = buffer.slice(0, instructionData.getSpan(buffer));
+
+        let ix = new TransactionInstruction({
+            keys: [
+                {pubkey: exchangeState, isSigner: false, isWritable: true},
+                {pubkey: exchangeWallet.publicKey, isSigner: true, isWritable: false},
+            ],
+            data: instructionBuffer,
+            programId: exchangeProgram,
+        });
+
+        let tx = new Transaction();
+        tx.recentBlockhash = (await connection.getLatestBlockhash('finalized')).blockhash;
+        tx.feePayer = exchangeWallet.publicKey;
+        tx.add(ix);
+
+        let sim_result = await connection.simulateTransaction(tx);
+        console.log("logs : {}", sim_result.value.logs);
+    });
+
+    function findUserStats(wallet: PublicKey): PublicKey {
+        return PublicKey.findProgramAddressSync(
+            [Buffer.from("user_stats"), wallet.toBuffer()],
+            exchangeProgram
+        )[0];
+    }
+
+    // swaps passing the stats count towards the fee tier of the client
+    it("Initialize user stats", async() => {
+
+        const instructionData = borsh.struct([
+            borsh.u8('version'),
+            borsh.u8('variant'),
+        ])
+        const buffer = Buffer.alloc(1000);
+        instructionData.encode({version: INSTRUCTION_VERSION, variant: 32}, buffer);
+        const instructionBuffer = buffer.slice(0, instructionData.getSpan(buffer));
+
+        let ix = new TransactionInstruction({
+            keys: [
+                {pubkey: exchangeState, isSigner: false, isWritable: false},
+                {pubkey: payer.publicKey, isSigner: true, isWritable: true},
+                {pubkey: clientWallet.publicKey, isSigner: false, isWritable: false},
+                {pubkey: findUserStats(clientWallet.publicKey), isSigner: false, isWritable: true},
+                {pubkey: system_program_id, isSigner: false, isWritable: false},
+            ],
+            data: instructionBuffer,
+            programId: exchangeProgram,
+        });
+
+        let tx = new Transaction();
+        tx.recentBlockhash = (await connection.getLatestBlockhash('finalized')).blockhash;
+        tx.feePayer = payer.publicKey;
+        tx.add(ix);
+
+        let sim_result = await connection.simulateTransaction(tx);
+        console.log("logs : {}", sim_result.value.logs);
+    });
+
     it("Deposit tokens", async() => {
 
         const exchangePda = PublicKey.findProgramAddressSync(
             [
-                Buffer.from( "toKeNpDaSeEd"),
-                //exchangeProgram.toBuffer(),
-                CUSTOM_MINT.toBuffer(),
-
+                exchangeWallet.publicKey.toBuffer(),
+                vaultSeed,
             ],
             exchangeProgram
         );
@@ -136,29 +385,34 @@ describe("Test1", async () => {
             exchangePda.at(0));
 
         const instructionData = borsh.struct([
+            borsh.u8('version'),
             borsh.u8('variant'),
             borsh.u64('amount'),
-            borsh.str('pda_seed'),
+            borsh.array(borsh.u8(), 16, 'pda_seed'),
             borsh.u8('bump_seed')
         ])
         const buffer = Buffer.alloc(1000);
         const value = new anchor.BN(5);
         instructionData.encode({
+            version: INSTRUCTION_VERSION,
             variant: 0,
             amount : value,
-            pda_seed: String("toKeNpDaSeEd"),
+            pda_seed: Array.from(vaultSeed),
             bump_seed: exchangePda.at(1)
         }, buffer);
         const instructionBuffer = buffer.slice(0, instructionData.getSpan(buffer));
 
         let ix = new TransactionInstruction({
             keys: [
+                {pubkey: exchangeState, isSigner: false, isWritable: true},
                 {pubkey: token_program_id, isSigner: false, isWritable: false},
-                {pubkey: CUSTOM_MINT, isSigner: false, isWritable: false},
+                {pubkey: USDC_MINT, isSigner: false, isWritable: false},
                 {pubkey: exchangeWallet.publicKey, isSigner: true, isWritable: true},
-                {pubkey: exchangeCustomAssociatedTokenAccount, isSigner: false, isWritable: true},
+                {pubkey: exchangeUsdcAssociatedTokenAccount, isSigner: false, isWritable: true},
                 {pubkey: pdaAccount, isSigner: false , isWritable: true},
-                {pubkey: exchangeProgram, isSigner: true, isWritable: true},
+                {pubkey: exchangeProgram, isSigner: false, isWritable: false},
+                {pubkey: system_program_id, isSigner: false, isWritable: false},
+                {pubkey: findTokenConfig(USDC_MINT), isSigner: false, isWritable: false},
             ],
             data: instructionBuffer,
             programId: exchangeProgram,
@@ -200,15 +454,15 @@ describe("Test1", async () => {
     it("Exchange sol to USDC", async () => {
 
         const instructionData = borsh.struct([
+            borsh.u8('version'),
             borsh.u8('variant'),
             borsh.u64('amount'),
-            borsh.str('pda_seed'),
-            borsh.u8('bump_seed')
         ])
         const buffer = Buffer.alloc(1000);
         const value = new anchor.BN(100000000);
         instructionData.encode(
             {
+                version: INSTRUCTION_VERSION,
                 variant: 2,
                 amount : value
             },
@@ -217,6 +471,7 @@ describe("Test1", async () => {
 
         let ix = new TransactionInstruction({
             keys: [
+                {pubkey: exchangeState, isSigner: false, isWritable: false},
                 {pubkey: token_program_id, isSigner: false, isWritable: false},
                 {pubkey: exchangeProgram, isSigner: false, isWritable: false},
                 {pubkey: NATIVE_MINT, isSigner: false, isWritable: false},
@@ -230,6 +485,15 @@ describe("Test1", async () => {
                 {pubkey: solToUsdDataFeedAccount, isSigner: false, isWritable: false},
                 {pubkey: usdcToUsdDataFeedAccount, isSigner: false, isWritable: false},
                 {pubkey: chainLinkProgramId, isSigner: false, isWritable: false},
+                {pubkey: findTokenConfig(NATIVE_MINT), isSigner: false, isWritable: false},
+                {pubkey: findTokenConfig(USDC_MINT), isSigner: false, isWritable: true},
+                {pubkey: findPairConfig(NATIVE_MINT, USDC_MINT), isSigner: false, isWritable: true},
+                {pubkey: findPriceHistory(NATIVE_MINT), isSigner: false, isWritable: true},
+                {pubkey: findPriceHistory(USDC_MINT), isSigner: false, isWritable: true},
+                {pubkey: findFeeVault(USDC_MINT), isSigner: false, isWritable: true},
+                // no referrer
+                {pubkey: exchangeProgram, isSigner: false, isWritable: false},
+                {pubkey: findUserStats(clientWallet.publicKey), isSigner: false, isWritable: true},
             ],
             data: instructionBuffer,
             programId: exchangeProgram,
@@ -263,15 +527,15 @@ describe("Test1", async () => {
     it("Exchange USDC to sol", async () => {
 
         const instructionData = borsh.struct([
+            borsh.u8('version'),
             borsh.u8('variant'),
             borsh.u64('amount'),
-            borsh.str('pda_seed'),
-            borsh.u8('bump_seed')
         ])
         const buffer = Buffer.alloc(1000);
         const value = new anchor.BN(5000000);  // 5 usdc
         instructionData.encode(
             {
+                version: INSTRUCTION_VERSION,
                 variant: 3,
                 amount : value,
             },
@@ -280,6 +544,7 @@ describe("Test1", async () => {
 
         let ix = new TransactionInstruction({
             keys: [
+                {pubkey: exchangeState, isSigner: false, isWritable: false},
                 {pubkey: token_program_id, isSigner: false, isWritable: false},
                 {pubkey: exchangeProgram, isSigner: true, isWritable: false},
                 {pubkey: USDC_MINT, isSigner: false, isWritable: false},
@@ -293,6 +558,15 @@ describe("Test1", async () => {
                 {pubkey: usdcToUsdDataFeedAccount, isSigner: false, isWritable: false},
                 {pubkey: solToUsdDataFeedAccount, isSigner: false, isWritable: false},
                 {pubkey: chainLinkProgramId, isSigner: false, isWritable: false},
+                {pubkey: findTokenConfig(USDC_MINT), isSigner: false, isWritable: false},
+                {pubkey: findTokenConfig(NATIVE_MINT), isSigner: false, isWritable: true},
+                {pubkey: findPairConfig(NATIVE_MINT, USDC_MINT), isSigner: false, isWritable: true},
+                {pubkey: findPriceHistory(USDC_MINT), isSigner: false, isWritable: true},
+                {pubkey: findPriceHistory(NATIVE_MINT), isSigner: false, isWritable: true},
+                {pubkey: findFeeVault(NATIVE_MINT), isSigner: false, isWritable: true},
+                // no referrer
+                {pubkey: exchangeProgram, isSigner: false, isWritable: false},
+                {pubkey: findUserStats(clientWallet.publicKey), isSigner: false, isWritable: true},
             ],
             data: instructionBuffer,
             programId: exchangeProgram,
@@ -326,4 +600,44 @@ describe("Test1", async () => {
         // );
         // console.log("transaction: {}", committed)
     });
+
+    it("Withdraw fees", async () => {
+
+        const instructionData = borsh.struct([
+            borsh.u8('version'),
+            borsh.u8('variant'),
+            borsh.u64('amount'),
+        ])
+        const buffer = Buffer.alloc(1000);
+        instructionData.encode(
+            {
+                version: INSTRUCTION_VERSION,
+                variant: 28,
+                amount : new anchor.BN(1000),
+            },
+            buffer);
+        const instructionBuffer = buffer.slice(0, instructionData.getSpan(buffer));
+
+        let ix = new TransactionInstruction({
+            keys: [
+                {pubkey: exchangeState, isSigner: false, isWritable: false},
+                {pubkey: exchangeWallet.publicKey, isSigner: true, isWritable: false},
+                {pubkey: token_program_id, isSigner: false, isWritable: false},
+                {pubkey: USDC_MINT, isSigner: false, isWritable: false},
+                {pubkey: findTokenConfig(USDC_MINT), isSigner: false, isWritable: true},
+                {pubkey: findFeeVault(USDC_MINT), isSigner: false, isWritable: true},
+                {pubkey: exchangeUsdcAssociatedTokenAccount, isSigner: false, isWritable: true},
+            ],
+            data: instructionBuffer,
+            programId: exchangeProgram,
+        });
+
+        let tx = new Transaction();
+        tx.recentBlockhash = (await connection.getLatestBlockhash('finalized')).blockhash;
+        tx.feePayer = exchangeWallet.publicKey;
+        tx.add(ix);
+
+        let sim_result = await connection.simulateTransaction(tx);
+        console.log("logs : {}", sim_result.value.logs);
+    });
 });